@@ -1,16 +1,54 @@
 mod auth;
+mod list;
+mod log;
+mod logout;
+mod sessions;
 mod share;
+mod status;
+mod stop;
 
 use async_trait::async_trait;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
+pub struct Cli {
+    /// Url of the gph server, such as `https://gph.example.com`
+    #[clap(long, global = true, env = "GPH_SERVER")]
+    pub server: Option<String>,
+
+    /// Profile from the config file to use; each profile keeps its own session token
+    #[clap(long, global = true, env = "GPH_PROFILE")]
+    pub profile: Option<String>,
+
+    #[clap(subcommand)]
+    pub command: CliCommand,
+}
+
+#[derive(Subcommand, Debug)]
 pub enum CliCommand {
     /// Signup using GitHub oauth2
     Auth(auth::Auth),
 
+    /// Revoke the session token and remove it from this machine
+    Logout(logout::Logout),
+
+    /// List the devices signed in to your account, or sign one out
+    Sessions(sessions::Sessions),
+
     ///  Share git repository
     Share(share::Share),
+
+    /// Show who fetched or pushed your shared repositories
+    Log(log::Log),
+
+    /// Show whether you are authenticated and which shares are active
+    Status(status::Status),
+
+    /// List active shares
+    List(list::List),
+
+    /// Stop a share and clean up its temporary repository
+    Stop(stop::Stop),
 }
 
 #[async_trait]
@@ -18,7 +56,13 @@ impl CommandExecutable for CliCommand {
     async fn execute(self) -> anyhow::Result<()> {
         match self {
             Self::Auth(auth) => auth.execute().await,
+            Self::Logout(logout) => logout.execute().await,
+            Self::Sessions(sessions) => sessions.execute().await,
             Self::Share(open) => open.execute().await,
+            Self::Log(log) => log.execute().await,
+            Self::Status(status) => status.execute().await,
+            Self::List(list) => list.execute().await,
+            Self::Stop(stop) => stop.execute().await,
         }
     }
 }
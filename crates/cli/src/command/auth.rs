@@ -1,69 +1,160 @@
+mod device;
+
 use crate::command::CommandExecutable;
-use crate::util::{session_token_path, HTTP_SERVER_ADDR};
+use crate::util::{session_token_path, server_url};
 use async_trait::async_trait;
 use axum::extract::{Query, State};
+use axum::response::Html;
 use axum::Router;
 use clap::Args;
 use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
 
 #[derive(Args, Debug, Clone)]
-pub struct Auth;
+pub struct Auth {
+    /// Log in by entering a code on another device, for machines without a browser
+    #[clap(long, action)]
+    pub device: bool,
+
+    /// Port of the local callback server; a free port is chosen if omitted
+    #[clap(long)]
+    pub port: Option<u16>,
+
+    /// Identity provider to log in with, such as `github`, `gitlab` or `gitea`; the server's default if omitted
+    #[clap(long)]
+    pub provider: Option<String>,
+}
 
 #[async_trait]
 impl CommandExecutable for Auth {
     async fn execute(self) -> anyhow::Result<()> {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(1);
-        start_http_server(7740, tx);
-        webbrowser::open(&format!("{HTTP_SERVER_ADDR}/oauth2/auth"))?;
+        if self.device {
+            return device::auth_with_device_code(self.provider).await;
+        }
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, self.port.unwrap_or(0))).await?;
+        let redirect_uri = format!("http://{}/oauth2/callback", listener.local_addr()?);
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<AuthCallback>(1);
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server = start_http_server(listener, tx, shutdown_rx);
 
-        let auth_code = rx
+        // Only this process knows the nonce, so a code and state delivered to the callback by anyone else
+        // are rejected by the server.
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let mut auth_url = reqwest::Url::parse(&server_url("/oauth2/auth"))?;
+        auth_url.query_pairs_mut()
+            .append_pair("redirect_uri", &redirect_uri)
+            .append_pair("nonce", &nonce);
+        if let Some(provider) = &self.provider {
+            auth_url.query_pairs_mut().append_pair("provider", provider);
+        }
+        if webbrowser::open(auth_url.as_str()).is_err() {
+            println!("Open {auth_url} in your browser to log in.");
+        }
+
+        let callback = rx
             .recv()
             .await
             .ok_or_else(|| anyhow::anyhow!("Failed to recv auth code"))?;
-        let session_token = register_user(auth_code).await?;
-        std::fs::write(session_token_path(), session_token)?;
-        println!("{}", colored(255, 255, 0, "Success!"));
-        Ok(())
+        let result = match callback.result {
+            Ok((code, state)) => register_user(code, state, nonce).await,
+            Err(error) => Err(anyhow::anyhow!("GitHub login failed: {error}")),
+        };
+        let _ = callback.reply.send(result.is_ok());
+        let _ = shutdown_tx.send(());
+        // Browsers may hold the connection open, so don't wait on it forever.
+        let _ = tokio::time::timeout(Duration::from_secs(3), server).await;
+        save_session_token(&result?)
     }
 }
 
+fn save_session_token(session_token: &str) -> anyhow::Result<()> {
+    std::fs::write(session_token_path(), session_token)?;
+    println!("{}", colored(255, 255, 0, "Success!"));
+    Ok(())
+}
+
+fn device_name() -> String {
+    gethostname::gethostname().to_string_lossy().into_owned()
+}
+
+/// What GitHub redirected the browser back with: the authorization code and state,
+/// both of which the server needs to finish the login, or an error.
+///
+/// The callback page waits on `reply` to tell the user whether the login succeeded.
+struct AuthCallback {
+    result: Result<(String, String), String>,
+    reply: oneshot::Sender<bool>,
+}
+
 fn colored(r: i32, g: i32, b: i32, text: &str) -> String {
     format!("\x1B[38;2;{};{};{}m{}\x1B[0m", r, g, b, text)
 }
 
-fn start_http_server(port: u64, tx: Sender<String>) {
+fn start_http_server(
+    listener: TcpListener,
+    tx: Sender<AuthCallback>,
+    shutdown_rx: oneshot::Receiver<()>,
+) -> JoinHandle<std::io::Result<()>> {
+    let router = Router::new()
+        .route("/oauth2/callback", axum::routing::get(oauth2_callback))
+        .with_state(tx);
     tokio::spawn(async move {
-        let listener = TcpListener::bind(format!("0.0.0.0:{port}"))
-            .await
-            .expect("Failed to create tcp listener");
-        let router = Router::new()
-            .route("/oauth2/callback", axum::routing::get(oauth2_callback))
-            .with_state(tx);
         axum::serve(listener, router)
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.await;
+            })
             .await
-            .expect("Failed to start http server");
-    });
+    })
 }
 
 async fn oauth2_callback(
     Query(mut query): Query<HashMap<String, String>>,
-    State(tx): State<Sender<String>>,
-) {
-    if let Some(code) = query.remove("code") {
-        tx.send(code).await.expect("Failed to pass auth code");
+    State(tx): State<Sender<AuthCallback>>,
+) -> Html<String> {
+    let result = match (query.remove("code"), query.remove("state")) {
+        (Some(code), Some(state)) => Ok((code, state)),
+        _ => Err(query
+            .remove("error_description")
+            .or_else(|| query.remove("error"))
+            .unwrap_or_else(|| "missing code".to_string())),
+    };
+    let (reply, succeeded) = oneshot::channel();
+    if tx.send(AuthCallback { result, reply }).await.is_err() {
+        return callback_page(false);
     }
+    callback_page(succeeded.await.unwrap_or(false))
+}
+
+fn callback_page(succeeded: bool) -> Html<String> {
+    let (title, message) = if succeeded {
+        ("Logged in to git_phantom", "You can close this tab and return to the terminal.")
+    } else {
+        ("Login failed", "Check the terminal for details and run `gph auth` again.")
+    };
+    Html(format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{title}</title></head>\
+        <body style=\"font-family: sans-serif; text-align: center; margin-top: 4em\">\
+        <h1>{title}</h1><p>{message}</p></body></html>"
+    ))
 }
 
-async fn register_user(auth_code: String) -> anyhow::Result<String> {
+async fn register_user(code: String, state: String, nonce: String) -> anyhow::Result<String> {
     let session_token = reqwest::ClientBuilder::new()
         .use_rustls_tls()
         .build()?
-        .put(format!(
-            "{HTTP_SERVER_ADDR}/oauth2/register?code={auth_code}"
-        ))
+        .put(server_url("/oauth2/register"))
+        .query(&[
+            ("code", code),
+            ("state", state),
+            ("nonce", nonce),
+            ("device", device_name()),
+        ])
         .send()
         .await?
         .error_for_status()?
@@ -0,0 +1,43 @@
+use crate::command::auth::{colored, device_name, save_session_token};
+use crate::util::{ensure_success, http_client, server_url};
+use gph_core::types::{DeviceAuthorization, DeviceTokenRequest, DeviceTokenResponse};
+use std::time::Duration;
+
+/// Logs in without a browser on this machine: the user enters the printed code at the printed URL
+/// on any other device, while this machine polls the server until the login completes.
+pub async fn auth_with_device_code(provider: Option<String>) -> anyhow::Result<()> {
+    let client = http_client()?;
+    let mut request = client.post(server_url("/oauth2/device/code"));
+    if let Some(provider) = &provider {
+        request = request.query(&[("provider", provider)]);
+    }
+    let response = request
+        .send()
+        .await?;
+    let authorization = ensure_success(response).await?.json::<DeviceAuthorization>().await?;
+    println!(
+        "Open {} and enter the code {}",
+        colored(255, 255, 0, &authorization.verification_uri),
+        colored(0, 255, 255, &authorization.user_code),
+    );
+
+    let request = DeviceTokenRequest {
+        device_code: authorization.device_code,
+        device_name: Some(device_name()),
+        provider,
+    };
+    let mut interval = Duration::from_secs(authorization.interval);
+    loop {
+        tokio::time::sleep(interval).await;
+        let response = client
+            .post(server_url("/oauth2/device/token"))
+            .json(&request)
+            .send()
+            .await?;
+        match ensure_success(response).await?.json::<DeviceTokenResponse>().await? {
+            DeviceTokenResponse::Pending => {}
+            DeviceTokenResponse::SlowDown => interval += Duration::from_secs(5),
+            DeviceTokenResponse::Complete { session_token } => return save_session_token(&session_token),
+        }
+    }
+}
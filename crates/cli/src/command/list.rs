@@ -0,0 +1,66 @@
+use crate::command::CommandExecutable;
+use crate::local_share::LocalShare;
+use crate::util::{colored_terminal_text, ensure_success, http_client, read_session_token, server_url};
+use async_trait::async_trait;
+use clap::Args;
+use gph_core::types::ShareInfo;
+
+#[derive(Debug, Clone, Args)]
+pub struct List;
+
+#[async_trait]
+impl CommandExecutable for List {
+    async fn execute(self) -> anyhow::Result<()> {
+        let session_token = read_session_token()?;
+        print_shares(&session_token).await
+    }
+}
+
+pub async fn print_shares(session_token: &str) -> anyhow::Result<()> {
+    let local_shares = LocalShare::load_all()?;
+    let server_shares = fetch_shares(session_token).await?;
+    if local_shares.is_empty() && server_shares.is_empty() {
+        println!("No active shares.");
+        return Ok(());
+    }
+
+    for share in &server_shares {
+        let repository = share.repository.as_deref().unwrap_or("-");
+        let local_share = local_shares.iter().find(|local| local.repository == repository);
+        println!(
+            "{} {} since {} clones {} guests {} {}",
+            colored_terminal_text(255, 255, 0, repository),
+            if share.readonly { "read-only" } else { "read-write" },
+            share.opened_at,
+            match share.max_clones {
+                Some(max_clones) => format!("{}/{max_clones}", share.clone_count),
+                None => share.clone_count.to_string(),
+            },
+            share.guest_count,
+            local_share
+                .map(|local| local.working_dir.display().to_string())
+                .unwrap_or_else(|| "(started on another machine)".to_string()),
+        );
+    }
+    for local_share in &local_shares {
+        if server_shares.iter().any(|share| share.repository.as_deref() == Some(local_share.repository.as_str())) {
+            continue;
+        }
+        println!(
+            "{} {} {}",
+            colored_terminal_text(255, 255, 0, &local_share.repository),
+            colored_terminal_text(255, 0, 0, "(not open on server)"),
+            local_share.working_dir.display(),
+        );
+    }
+    Ok(())
+}
+
+pub async fn fetch_shares(session_token: &str) -> anyhow::Result<Vec<ShareInfo>> {
+    let response = http_client()?
+        .get(server_url("/shares"))
+        .bearer_auth(session_token)
+        .send()
+        .await?;
+    Ok(ensure_success(response).await?.json().await?)
+}
@@ -0,0 +1,51 @@
+use crate::command::CommandExecutable;
+use crate::util::{colored_terminal_text, ensure_success, format_bytes, http_client, read_session_token, server_url};
+use async_trait::async_trait;
+use clap::Args;
+use gph_core::types::AccessLogEntry;
+
+#[derive(Debug, Clone, Args)]
+pub struct Log {
+    /// Maximum number of entries to show
+    #[clap(short, long, default_value_t = 50)]
+    pub limit: u32,
+}
+
+#[async_trait]
+impl CommandExecutable for Log {
+    async fn execute(self) -> anyhow::Result<()> {
+        let session_token = read_session_token()?;
+        let response = http_client()?
+            .get(server_url("/log"))
+            .query(&[("limit", self.limit)])
+            .bearer_auth(session_token)
+            .send()
+            .await?;
+        let logs = ensure_success(response).await?.json::<Vec<AccessLogEntry>>().await?;
+        if logs.is_empty() {
+            println!("No guests have accessed your shares yet.");
+        }
+        for log in logs.iter().rev() {
+            println!("{}", format_log(log));
+        }
+        Ok(())
+    }
+}
+
+fn format_log(log: &AccessLogEntry) -> String {
+    let status = if (200..300).contains(&log.status) {
+        colored_terminal_text(0, 255, 0, &log.status.to_string())
+    } else {
+        colored_terminal_text(255, 0, 0, &log.status.to_string())
+    };
+    format!(
+        "{} {status} {:<16} {:<15} {} in:{} out:{} {}",
+        log.started_at,
+        log.service.as_deref().unwrap_or("-"),
+        log.remote_addr.as_deref().unwrap_or("-"),
+        log.repository,
+        format_bytes(log.bytes_in as u64),
+        format_bytes(log.bytes_out as u64),
+        log.user_agent.as_deref().unwrap_or("-"),
+    )
+}
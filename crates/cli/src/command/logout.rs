@@ -0,0 +1,29 @@
+use crate::command::CommandExecutable;
+use crate::util::{colored_terminal_text, http_client, server_url, session_token_path};
+use async_trait::async_trait;
+use clap::Args;
+
+#[derive(Debug, Clone, Args)]
+pub struct Logout;
+
+#[async_trait]
+impl CommandExecutable for Logout {
+    async fn execute(self) -> anyhow::Result<()> {
+        let Ok(session_token) = std::fs::read_to_string(session_token_path()) else {
+            println!("Not logged in.");
+            return Ok(());
+        };
+        let response = http_client()?
+            .delete(server_url("/session"))
+            .bearer_auth(session_token)
+            .send()
+            .await?;
+        // The token is already unusable if the server rejects it, so the local file is removed anyway.
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            anyhow::bail!("Failed to revoke session token: {}", response.text().await?);
+        }
+        std::fs::remove_file(session_token_path())?;
+        println!("{}", colored_terminal_text(255, 255, 0, "Logged out"));
+        Ok(())
+    }
+}
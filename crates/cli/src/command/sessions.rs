@@ -0,0 +1,60 @@
+use crate::command::CommandExecutable;
+use crate::util::{colored_terminal_text, ensure_success, http_client, read_session_token, server_url};
+use anyhow::bail;
+use async_trait::async_trait;
+use clap::Args;
+use gph_core::types::SessionInfo;
+
+#[derive(Debug, Clone, Args)]
+pub struct Sessions {
+    /// Sign out the session whose id starts with the given prefix
+    #[clap(long)]
+    pub revoke: Option<String>,
+}
+
+#[async_trait]
+impl CommandExecutable for Sessions {
+    async fn execute(self) -> anyhow::Result<()> {
+        let session_token = read_session_token()?;
+        let sessions = fetch_sessions(&session_token).await?;
+        let Some(prefix) = self.revoke else {
+            for session in &sessions {
+                println!("{}", format_session(session));
+            }
+            return Ok(());
+        };
+
+        let mut matches = sessions.iter().filter(|session| session.id.to_string().starts_with(&prefix));
+        let (Some(session), None) = (matches.next(), matches.next()) else {
+            bail!("No single session matches `{prefix}`");
+        };
+        let response = http_client()?
+            .delete(server_url(&format!("/sessions/{}", session.id)))
+            .bearer_auth(&session_token)
+            .send()
+            .await?;
+        ensure_success(response).await?;
+        println!("{} {}", colored_terminal_text(255, 255, 0, "Revoked"), format_session(session));
+        Ok(())
+    }
+}
+
+async fn fetch_sessions(session_token: &str) -> anyhow::Result<Vec<SessionInfo>> {
+    let response = http_client()?
+        .get(server_url("/sessions"))
+        .bearer_auth(session_token)
+        .send()
+        .await?;
+    Ok(ensure_success(response).await?.json().await?)
+}
+
+fn format_session(session: &SessionInfo) -> String {
+    format!(
+        "{} {:<20} created {} last used {}{}",
+        &session.id.to_string()[..8],
+        session.device_name.as_deref().unwrap_or("-"),
+        session.created_at,
+        session.last_used_at,
+        if session.current { colored_terminal_text(0, 255, 0, " (this device)") } else { String::new() },
+    )
+}
@@ -1,13 +1,19 @@
+mod activity;
+mod fetch;
+mod follow;
+
+use crate::command::share::activity::{Activity, ActivityFeed};
 use crate::command::CommandExecutable;
-use crate::util::{app_dir, colored_terminal_text, session_token_path, OutputErr, HTTP_SERVER_ADDR, WS_SERVER_ADDR};
-use anyhow::{anyhow, bail};
+use crate::local_share::LocalShare;
+use crate::util::{colored_terminal_text, git_root, ensure_success, http_client, read_session_token, refresh_session_token, OutputErr, server_url, ws_url};
+use anyhow::anyhow;
 use arboard::Clipboard;
 use async_trait::async_trait;
 use clap::Args;
 use futures_util::{SinkExt, StreamExt};
-use gph_core::types::{GitRequest, GitResponse};
+use gph_core::types::{GitRequest, GitResponse, ShareOptions};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Stdio};
 use std::sync::Arc;
 use tokio::io::AsyncWriteExt;
@@ -31,15 +37,26 @@ pub struct Share {
     /// Forbid other users from pushing to a shared repository
     #[clap(long, action)]
     pub readonly: bool,
+
+    /// Refuse further fetches after the repository has been cloned this many times
+    #[clap(long, value_parser = clap::value_parser!(u32).range(..=i32::MAX as i64))]
+    pub max_clones: Option<u32>,
+
+    /// Also write guest activity to the file as JSON lines
+    #[clap(long)]
+    pub activity_log: Option<PathBuf>,
+
+    /// Push local branches to a shared repository whenever they change
+    #[clap(long, action)]
+    pub follow: bool,
 }
 
 #[async_trait]
 impl CommandExecutable for Share {
     async fn execute(self) -> anyhow::Result<()> {
-        let session_token = std::fs::read_to_string(session_token_path())
-            .map_err(|e| anyhow!("Failed to read session token.\nIf you haven't authenticated yet, run `gph auth`\n{e:?}"))?;
+        let session_token = refresh_session_token(&read_session_token()?).await?;
 
-        let repository_name = match self.repository {
+        let repository_name = match self.repository.clone() {
             Some(repository) => repository,
             None => env::current_dir()?
                 .file_name()
@@ -52,69 +69,125 @@ impl CommandExecutable for Share {
 
         let _ = std::fs::remove_dir_all(git_root()?.join(&repository_name));
         git_init(&repository_name).await?;
-        let result = execute_share(
+        let local_share = LocalShare::new(repository_name.clone(), env::current_dir()?);
+        if let Err(e) = local_share.save() {
+            eprintln!("Failed to save local share: {e}");
+        }
+        let result = self.execute_share(
             &session_token,
             &git_remote_url,
             &repository_name,
-            self.no_push,
-            self.readonly,
         ).await;
 
-        if let Err(e) = git_remote_remove().await {
+        let shared_repository = git_root()?.join(&repository_name);
+        let keep_shared_repository = warn_unfetched_guest_branches(&shared_repository, &local_share.working_dir).await;
+        if let Err(e) = git_remote_remove(&local_share.working_dir).await {
             eprintln!("{e}");
         }
-        if let Err(e) = std::fs::remove_dir_all(git_root()?.join(repository_name)) {
-            eprintln!("{e}");
+        if !keep_shared_repository {
+            if let Err(e) = std::fs::remove_dir_all(shared_repository) {
+                eprintln!("{e}");
+            }
         }
+        let _ = LocalShare::remove(&repository_name);
         result?;
 
         Ok(())
     }
 }
 
-async fn execute_share(
-    session_token: &str,
-    git_remote_url: &str,
-    repository_name: &str,
-    no_push: bool,
-    readonly: bool,
-) -> anyhow::Result<()> {
-    let _ = git_remote_remove().await;
-    git_add_remote(repository_name).await?;
-    if !no_push {
-        git_push_all().await?;
-    }
-    if !readonly {
-        git_set_http_receive_pack(repository_name).await?;
+impl Share {
+    async fn execute_share(
+        &self,
+        session_token: &str,
+        git_remote_url: &str,
+        repository_name: &str,
+    ) -> anyhow::Result<()> {
+        let _ = git_remote_remove(&env::current_dir()?).await;
+        git_add_remote(repository_name).await?;
+        if !self.no_push {
+            git_push_all().await?;
+        }
+        if !self.readonly {
+            git_set_http_receive_pack(repository_name).await?;
+        }
+        let feed = ActivityFeed::new(git_root()?.join(repository_name), self.activity_log.clone())?;
+        let options = ShareOptions {
+            repository: Some(repository_name.to_string()),
+            readonly: self.readonly,
+            max_clones: self.max_clones,
+        };
+        let ws = connect_websocket(session_token, &options).await?;
+
+        let mut clipboard = Clipboard::new()?;
+        if let Err(e) = clipboard.set_text(git_remote_url) {
+            eprintln!("{e}");
+        }
+
+        println!("{} {git_remote_url}", colored_terminal_text(255, 255, 0, "Git remote url:"));
+        println!("{}", colored_terminal_text(255, 255, 0, "Added git-remote `gph`"));
+        if let Some(max_clones) = options.max_clones {
+            println!("{}", colored_terminal_text(255, 255, 0, &format!("`gph` stops serving fetches after {max_clones} clone(s)")));
+        }
+        if self.follow {
+            println!("{}", colored_terminal_text(255, 255, 0, "Local branches are published to `gph` whenever they change"));
+        }
+        println!("{}", colored_terminal_text(255, 255, 0, "`gph` is destroyed when the forked shell is terminated by `exit`.\n"));
+
+        let follow = self.follow;
+        tokio::select! {
+            result = tokio::spawn(async move { websocket_handle(ws, feed).await }) => result??,
+            result = spawn_shell() => result?,
+            _ = async move {
+                if follow {
+                    follow::follow_local_branches().await
+                } else {
+                    std::future::pending().await
+                }
+            } => {}
+        }
+        Ok(())
     }
+}
 
-   let config = rustls_platform_verifier::tls_config();
+async fn connect_websocket(
+    session_token: &str,
+    options: &ShareOptions,
+) -> anyhow::Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let config = rustls_platform_verifier::tls_config();
     let connector = tokio_tungstenite::Connector::Rustls(Arc::new(config));
-    let mut request = format!("{WS_SERVER_ADDR}/share").into_client_request()?;
+    let query = serde_urlencoded::to_string(options)?;
+    let mut request = ws_url(&format!("/share?{query}")).into_client_request()?;
     request
         .headers_mut()
         .insert("Authorization", format!("Bearer {session_token}").parse()?);
     let (ws, _) = tokio_tungstenite::connect_async_tls_with_config(request, None, false, Some(connector))
         .await
-        .map_err(|e|anyhow!("Failed to connect websocket: \n{e}"))?;
+        .map_err(|e| anyhow!("Failed to connect websocket: \n{e}"))?;
+    Ok(ws)
+}
 
-    let mut clipboard = Clipboard::new()?;
-    if let Err(e) = clipboard.set_text(git_remote_url) {
-        eprintln!("{e}");
+/// Fetches the branches pushed by guests into `working_dir` and returns true if some could not be fetched,
+/// in which case the shared repository is kept so that their commits are not lost.
+pub(crate) async fn warn_unfetched_guest_branches(shared_repository: &Path, working_dir: &Path) -> bool {
+    let working_dir = Some(working_dir);
+    let mut unfetched = fetch::unfetched_guest_branches(shared_repository, working_dir).await.unwrap_or_default();
+    if !unfetched.is_empty() && fetch::git_fetch(working_dir).await.is_ok() {
+        unfetched = fetch::unfetched_guest_branches(shared_repository, working_dir).await.unwrap_or_default();
     }
-
-    println!("{} {git_remote_url}", colored_terminal_text(255, 255, 0, "Git remote url:"));
-    println!("{}", colored_terminal_text(255, 255, 0, "Added git-remote `gph`"));
-    println!("{}", colored_terminal_text(255, 255, 0,"`gph` is destroyed when the forked shell is terminated by `exit`.\n"));
-
-    tokio::select! {
-            result= tokio::spawn(async move{ websocket_handle(ws).await }) => result??,
-            result = spawn_shell() => result?
+    if unfetched.is_empty() {
+        return false;
     }
-    Ok(())
+    eprintln!(
+        "{} {}\nThe shared repository is kept at {}",
+        colored_terminal_text(255, 0, 0, "Branches pushed by guests were never fetched:"),
+        unfetched.join(", "),
+        shared_repository.display(),
+    );
+    true
 }
 
-fn change_repository_extension(repository: String) -> String {
+pub(crate) fn change_repository_extension(repository: String) -> String {
     if repository.ends_with(".git") {
         repository
     } else {
@@ -124,8 +197,13 @@ fn change_repository_extension(repository: String) -> String {
 
 async fn websocket_handle(
     mut ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    mut feed: ActivityFeed,
 ) -> anyhow::Result<()> {
     while let Some(Ok(message)) = ws.next().await {
+        if let Message::Close(Some(close_frame)) = &message {
+            println!("{} {}", colored_terminal_text(255, 255, 0, "Share closed by server:"), close_frame.reason);
+            break;
+        }
         let Ok(message) = message.to_text() else {
             continue;
         };
@@ -133,7 +211,10 @@ async fn websocket_handle(
             continue;
         };
         let request_id = git_request.id;
-        let output = execute_git_http_backend(git_request).await?;
+        let output = execute_git_http_backend(&git_request)
+            .await
+            .map_err(|e| anyhow::anyhow!("git http-backend failed (request id {}): {e}", request_id.0))?;
+        let activity = feed.report(&git_request, &output).await;
         ws.send(Message::Text(
             serde_json::to_string(&GitResponse {
                 id: request_id,
@@ -142,23 +223,23 @@ async fn websocket_handle(
                 .unwrap(),
         ))
             .await?;
+        if let Some(Activity::Pushed { updates }) = activity {
+            if let Err(e) = fetch::fetch_guest_push(&updates).await {
+                eprintln!("{} {e}", colored_terminal_text(255, 0, 0, "Failed to fetch guest push:"));
+            }
+        }
     }
     Ok(())
 }
 
 async fn create_git_remote_url(session_token: &str, repository_name: &str) -> anyhow::Result<String> {
-    let response = reqwest::ClientBuilder::new()
-        .use_rustls_tls()
-        .build()?
-        .get(format!("{HTTP_SERVER_ADDR}/user_id"))
+    let response = http_client()?
+        .get(server_url("/user_id"))
         .bearer_auth(session_token)
         .send()
         .await?;
-    if !response.status().is_success(){
-        bail!("{}", response.text().await?);
-    }
-    let user_id = response.text().await?;
-    Ok(format!("{HTTP_SERVER_ADDR}/git/{user_id}/{repository_name}"))
+    let user_id = ensure_success(response).await?.text().await?;
+    Ok(server_url(&format!("/git/{user_id}/{repository_name}")))
 }
 
 async fn git_init(repository: &str) -> std::io::Result<()> {
@@ -214,28 +295,30 @@ async fn git_push_all() -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn git_remote_remove() -> std::io::Result<()> {
+/// Removes the `gph` remote but keeps `refs/remotes/gph/*`, which hold the branches pushed by guests.
+pub(crate) async fn git_remote_remove(working_dir: &Path) -> std::io::Result<()> {
     Command::new("git")
-        .arg("remote")
-        .arg("rm")
-        .arg("gph")
+        .arg("config")
+        .arg("--remove-section")
+        .arg("remote.gph")
+        .current_dir(working_dir)
         .output()
         .await?
         .err_if_failed()?;
     Ok(())
 }
 
-async fn execute_git_http_backend(request: GitRequest) -> std::io::Result<Vec<u8>> {
+async fn execute_git_http_backend(request: &GitRequest) -> std::io::Result<Vec<u8>> {
     let mut cmd = Command::new("git");
     cmd.arg("http-backend");
 
-    if let Some(query) = request.query_string {
+    if let Some(query) = &request.query_string {
         cmd.env("QUERY_STRING", query);
     }
-    if let Some(content_length) = request.content_length {
+    if let Some(content_length) = &request.content_length {
         cmd.env("CONTENT_LENGTH", content_length);
     }
-    if let Some(content_type) = request.content_type {
+    if let Some(content_type) = &request.content_type {
         cmd.env("CONTENT_TYPE", content_type);
     }
 
@@ -246,7 +329,9 @@ async fn execute_git_http_backend(request: GitRequest) -> std::io::Result<Vec<u8
             "PATH_INFO",
             format!("/{}", request.path_info),
         )
-        .env("REQUEST_METHOD", request.required_method)
+        .env("REQUEST_METHOD", &request.required_method)
+        // The id the server logs and returns to the guest as `X-Request-Id`, for hooks to log.
+        .env("GPH_REQUEST_ID", request.id.0.to_string())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -270,14 +355,7 @@ async fn spawn_shell() -> anyhow::Result<()> {
     } else {
         Command::new("sh")
     };
-    cmd.spawn()?.wait_with_output().await?;
+    // The shell is killed if the share is closed from elsewhere, e.g. by `DELETE /shares/:id`.
+    cmd.kill_on_drop(true).spawn()?.wait_with_output().await?;
     Ok(())
 }
-
-fn git_root() -> std::io::Result<PathBuf> {
-    let dir = app_dir().join("git");
-    if !dir.exists() {
-        std::fs::create_dir_all(&dir).expect("Failed to create git root dir");
-    }
-    Ok(dir)
-}
@@ -0,0 +1,444 @@
+use crate::util::{colored_terminal_text, format_bytes, OutputErr};
+use gph_core::types::{GitRequest, RequestId};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::process::Command;
+
+pub const ZERO_ID: &str = "0000000000000000000000000000000000000000";
+const PACK_SIGNATURE: &[u8] = b"PACK\0\0\0\x02";
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Activity {
+    Cloned {
+        refs: Vec<String>,
+        bytes: u64,
+    },
+    Fetched {
+        refs: Vec<String>,
+        bytes: u64,
+    },
+    Pushed {
+        updates: Vec<RefUpdate>,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct RefUpdate {
+    pub old_id: String,
+    pub new_id: String,
+    pub refname: String,
+}
+
+#[derive(Serialize)]
+struct ActivityRecord<'a> {
+    timestamp: u64,
+    request_id: RequestId,
+    guest: &'a str,
+    #[serde(flatten)]
+    activity: &'a Activity,
+}
+
+/// Prints a line for each guest operation relayed to the shared repository.
+pub struct ActivityFeed {
+    repository: PathBuf,
+    log_file: Option<File>,
+}
+
+impl ActivityFeed {
+    pub fn new(repository: PathBuf, log_path: Option<PathBuf>) -> std::io::Result<Self> {
+        let log_file = match log_path {
+            Some(path) => Some(File::options().create(true).append(true).open(path)?),
+            None => None,
+        };
+        Ok(Self {
+            repository,
+            log_file,
+        })
+    }
+
+    pub async fn report(&mut self, request: &GitRequest, output: &[u8]) -> Option<Activity> {
+        let mut activity = parse_activity(request, output)?;
+        if let Activity::Cloned { refs, .. } | Activity::Fetched { refs, .. } = &mut activity {
+            self.resolve_refnames(refs).await;
+        }
+
+        let guest = request.remote_addr.as_deref().unwrap_or("guest");
+        println!("{}", describe(guest, &activity));
+        if let Some(log_file) = self.log_file.as_mut() {
+            let record = ActivityRecord {
+                timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                request_id: request.id,
+                guest,
+                activity: &activity,
+            };
+            if let Err(e) = writeln!(log_file, "{}", serde_json::to_string(&record).unwrap()) {
+                eprintln!("Failed to write activity log: {e}");
+            }
+        }
+        Some(activity)
+    }
+
+    /// Replaces the object ids wanted by a guest with the branch names pointing at them.
+    async fn resolve_refnames(&self, refs: &mut [String]) {
+        let Ok(refnames) = for_each_ref(&self.repository).await else {
+            return;
+        };
+        for object_id in refs.iter_mut() {
+            if let Some(refname) = refnames.get(object_id.as_str()) {
+                *object_id = refname.clone();
+            } else {
+                object_id.truncate(7);
+            }
+        }
+    }
+}
+
+async fn for_each_ref(repository: &PathBuf) -> std::io::Result<HashMap<String, String>> {
+    let output = Command::new("git")
+        .arg("for-each-ref")
+        .arg("--format=%(objectname) %(refname:short)")
+        .current_dir(repository)
+        .output()
+        .await?
+        .err_if_failed()?;
+    let mut refnames = HashMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some((object_id, refname)) = line.split_once(' ') {
+            refnames.entry(object_id.to_string()).or_insert_with(|| refname.to_string());
+        }
+    }
+    Ok(refnames)
+}
+
+fn describe(guest: &str, activity: &Activity) -> String {
+    let guest = colored_terminal_text(0, 255, 255, guest);
+    match activity {
+        Activity::Cloned { refs, bytes } => format!(
+            "{guest} {} {} ({})",
+            colored_terminal_text(0, 255, 0, "cloned"),
+            refs.join(", "),
+            format_bytes(*bytes),
+        ),
+        Activity::Fetched { refs, bytes } => format!(
+            "{guest} {} {} ({})",
+            colored_terminal_text(0, 255, 0, "fetched"),
+            refs.join(", "),
+            format_bytes(*bytes),
+        ),
+        Activity::Pushed { updates } => {
+            let updates = updates
+                .iter()
+                .map(describe_ref_update)
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{guest} {} {updates}", colored_terminal_text(255, 165, 0, "pushed"))
+        }
+    }
+}
+
+fn describe_ref_update(update: &RefUpdate) -> String {
+    let refname = update.refname.strip_prefix("refs/heads/").unwrap_or(&update.refname);
+    if update.old_id == ZERO_ID {
+        format!("{refname} (new) {}", short_id(&update.new_id))
+    } else if update.new_id == ZERO_ID {
+        format!("{refname} (deleted)")
+    } else {
+        format!("{refname} {}..{}", short_id(&update.old_id), short_id(&update.new_id))
+    }
+}
+
+fn short_id(object_id: &str) -> &str {
+    &object_id[..object_id.len().min(7)]
+}
+
+pub fn parse_activity(request: &GitRequest, output: &[u8]) -> Option<Activity> {
+    if request.required_method != "POST" {
+        return None;
+    }
+    if request.path_info.ends_with("/git-upload-pack") {
+        parse_upload_pack(&request.body, output)
+    } else if request.path_info.ends_with("/git-receive-pack") {
+        parse_receive_pack(&request.body, output)
+    } else {
+        None
+    }
+}
+
+/// Only the request that actually returns a packfile is reported,
+/// so that ref advertisements and negotiation rounds are not shown as fetches.
+fn parse_upload_pack(body: &[u8], output: &[u8]) -> Option<Activity> {
+    if !output.windows(PACK_SIGNATURE.len()).any(|window| window == PACK_SIGNATURE) {
+        return None;
+    }
+    let mut wants = Vec::new();
+    let mut has_haves = false;
+    for line in pkt_lines(body).flatten() {
+        let line = String::from_utf8_lossy(line);
+        if let Some(want) = line.strip_prefix("want ") {
+            if let Some(object_id) = want.split_whitespace().next().filter(|id| is_object_id(id)) {
+                wants.push(object_id.to_string());
+            }
+        } else if line.starts_with("have ") {
+            has_haves = true;
+        }
+    }
+    let bytes = output.len() as u64;
+    Some(if has_haves {
+        Activity::Fetched { refs: wants, bytes }
+    } else {
+        Activity::Cloned { refs: wants, bytes }
+    })
+}
+
+/// Reports the ref updates sent by the guest that `git-receive-pack` accepted.
+///
+/// The commands come from the guest, so only those with well-formed object ids and refnames
+/// that the report-status in `output` marks as `ok` are kept.
+fn parse_receive_pack(body: &[u8], output: &[u8]) -> Option<Activity> {
+    let accepted = accepted_refs(output, uses_side_band(body));
+    let mut updates = Vec::new();
+    for line in pkt_lines(body) {
+        let Some(line) = line else {
+            break;
+        };
+        let command = line.split(|b| *b == 0).next().unwrap_or_default();
+        let command = String::from_utf8_lossy(command);
+        let mut fields = command.split_whitespace();
+        if let (Some(old_id), Some(new_id), Some(refname)) = (fields.next(), fields.next(), fields.next()) {
+            if is_object_id(old_id) && is_object_id(new_id) && is_valid_refname(refname) && accepted.contains(refname) {
+                updates.push(RefUpdate {
+                    old_id: old_id.to_string(),
+                    new_id: new_id.to_string(),
+                    refname: refname.to_string(),
+                });
+            }
+        }
+    }
+    if updates.is_empty() {
+        None
+    } else {
+        Some(Activity::Pushed { updates })
+    }
+}
+
+/// Whether the guest asked for the report-status to be multiplexed with progress messages.
+fn uses_side_band(body: &[u8]) -> bool {
+    let Some(Some(first_command)) = pkt_lines(body).next() else {
+        return false;
+    };
+    let capabilities = first_command.split(|b| *b == 0).nth(1).unwrap_or_default();
+    String::from_utf8_lossy(capabilities)
+        .split_whitespace()
+        .any(|capability| capability == "side-band" || capability == "side-band-64k")
+}
+
+/// Collects the refs reported as `ok <refname>` in the report-status of the `http-backend` output.
+/// Refs reported as `ng <refname> <reason>` and refs missing from the report are not accepted.
+fn accepted_refs(output: &[u8], side_band: bool) -> HashSet<String> {
+    let body = output
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .map(|index| &output[index + 4..])
+        .unwrap_or(output);
+    let report = if side_band {
+        pkt_lines(body)
+            .flatten()
+            .filter_map(|packet| packet.strip_prefix(&[1]))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>()
+    } else {
+        body.to_vec()
+    };
+    pkt_lines(&report)
+        .flatten()
+        .filter_map(|line| std::str::from_utf8(line).ok()?.strip_prefix("ok ").map(String::from))
+        .collect()
+}
+
+/// A full SHA-1 or SHA-256 object id.
+pub fn is_object_id(object_id: &str) -> bool {
+    (object_id.len() == 40 || object_id.len() == 64) && object_id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Checks a refname against the rules of `git check-ref-format`,
+/// which also keeps control characters sent by a guest out of the owner's terminal.
+fn is_valid_refname(refname: &str) -> bool {
+    refname != "@"
+        && !refname.ends_with('/')
+        && !refname.ends_with('.')
+        && !refname.contains("..")
+        && !refname.contains("@{")
+        && !refname.contains("//")
+        && !refname.bytes().any(|b| b.is_ascii_control() || b" ~^:?*[\\".contains(&b))
+        && refname
+            .split('/')
+            .all(|component| !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock"))
+}
+
+/// Iterates over pkt-lines, yielding `None` for special packets such as flush and delimiter.
+///
+/// The iteration stops at the first malformed packet, which is usually the beginning of a packfile.
+fn pkt_lines(mut data: &[u8]) -> impl Iterator<Item=Option<&[u8]>> {
+    std::iter::from_fn(move || {
+        let len = std::str::from_utf8(data.get(..4)?)
+            .ok()
+            .and_then(|len| usize::from_str_radix(len, 16).ok())?;
+        if len < 4 {
+            data = &data[4..];
+            return Some(None);
+        }
+        let line = data.get(4..len)?;
+        data = &data[len..];
+        Some(Some(line.strip_suffix(b"\n").unwrap_or(line)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::share::activity::{is_valid_refname, parse_activity, Activity, RefUpdate, ZERO_ID};
+    use gph_core::types::GitRequest;
+
+    const OLD_ID: &str = "1111111111111111111111111111111111111111";
+    const NEW_ID: &str = "2222222222222222222222222222222222222222";
+
+    fn pkt_line(line: &str) -> String {
+        format!("{:04x}{line}", line.len() + 4)
+    }
+
+    fn post(path_info: &str, body: String) -> GitRequest {
+        GitRequest {
+            path_info: path_info.to_string(),
+            required_method: "POST".to_string(),
+            body: body.into_bytes(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cloned_if_no_haves() {
+        let body = format!("{}0000{}", pkt_line(&format!("want {NEW_ID} side-band-64k\n")), pkt_line("done\n"));
+        let output = b"0008NAK\nPACK\0\0\0\x02";
+        let activity = parse_activity(&post("sample.git/git-upload-pack", body), output);
+        assert_eq!(activity, Some(Activity::Cloned {
+            refs: vec![NEW_ID.to_string()],
+            bytes: output.len() as u64,
+        }));
+    }
+
+    #[test]
+    fn fetched_if_haves_exist() {
+        let body = format!(
+            "{}0001{}{}{}0000",
+            pkt_line("command=fetch\n"),
+            pkt_line(&format!("want {NEW_ID}\n")),
+            pkt_line(&format!("have {OLD_ID}\n")),
+            pkt_line("done\n"),
+        );
+        let activity = parse_activity(&post("sample.git/git-upload-pack", body), b"PACK\0\0\0\x02");
+        assert!(matches!(activity, Some(Activity::Fetched { .. })));
+    }
+
+    #[test]
+    fn no_activity_if_pack_not_sent() {
+        let body = format!("{}0000", pkt_line("command=ls-refs\n"));
+        let activity = parse_activity(&post("sample.git/git-upload-pack", body), b"0000");
+        assert_eq!(activity, None);
+    }
+
+    #[test]
+    fn pushed_ref_updates() {
+        let body = format!(
+            "{}{}0000PACK",
+            pkt_line(&format!("{OLD_ID} {NEW_ID} refs/heads/main\0report-status side-band-64k\n")),
+            pkt_line(&format!("{ZERO_ID} {NEW_ID} refs/heads/feature/x\n")),
+        );
+        let report = format!(
+            "{}{}{}0000",
+            pkt_line("unpack ok\n"),
+            pkt_line("ok refs/heads/main\n"),
+            pkt_line("ok refs/heads/feature/x\n"),
+        );
+        let output = format!("Content-Type: application/x-git-receive-pack-result\r\n\r\n{}0000", pkt_line(&format!("\x01{report}")));
+        let activity = parse_activity(&post("sample.git/git-receive-pack", body), output.as_bytes());
+        assert_eq!(activity, Some(Activity::Pushed {
+            updates: vec![
+                RefUpdate {
+                    old_id: OLD_ID.to_string(),
+                    new_id: NEW_ID.to_string(),
+                    refname: "refs/heads/main".to_string(),
+                },
+                RefUpdate {
+                    old_id: ZERO_ID.to_string(),
+                    new_id: NEW_ID.to_string(),
+                    refname: "refs/heads/feature/x".to_string(),
+                },
+            ]
+        }));
+    }
+
+    #[test]
+    fn rejected_ref_updates_not_reported() {
+        let body = format!(
+            "{}{}0000PACK",
+            pkt_line(&format!("{OLD_ID} {NEW_ID} refs/heads/main\0report-status\n")),
+            pkt_line(&format!("{ZERO_ID} {NEW_ID} refs/heads/feature\n")),
+        );
+        let output = format!(
+            "\r\n\r\n{}{}{}0000",
+            pkt_line("unpack ok\n"),
+            pkt_line("ng refs/heads/main non-fast-forward\n"),
+            pkt_line("ok refs/heads/feature\n"),
+        );
+        let activity = parse_activity(&post("sample.git/git-receive-pack", body), output.as_bytes());
+        assert_eq!(activity, Some(Activity::Pushed {
+            updates: vec![RefUpdate {
+                old_id: ZERO_ID.to_string(),
+                new_id: NEW_ID.to_string(),
+                refname: "refs/heads/feature".to_string(),
+            }]
+        }));
+    }
+
+    #[test]
+    fn no_activity_if_push_rejected() {
+        let body = format!("{}0000PACK", pkt_line(&format!("{OLD_ID} {NEW_ID} refs/heads/main\0report-status\n")));
+        let output = format!("\r\n\r\n{}{}0000", pkt_line("unpack ok\n"), pkt_line("ng refs/heads/main pre-receive hook declined\n"));
+        assert_eq!(parse_activity(&post("sample.git/git-receive-pack", body), output.as_bytes()), None);
+    }
+
+    #[test]
+    fn malformed_ref_updates_not_reported() {
+        let refname = "refs/heads/\x1b[2Jmain";
+        let body = format!(
+            "{}{}0000PACK",
+            pkt_line(&format!("{OLD_ID} {NEW_ID} {refname}\0report-status\n")),
+            pkt_line(&format!("{OLD_ID} --output=/tmp/x refs/heads/other\n")),
+        );
+        let output = format!("\r\n\r\n{}{}0000", pkt_line(&format!("ok {refname}\n")), pkt_line("ok refs/heads/other\n"));
+        assert_eq!(parse_activity(&post("sample.git/git-receive-pack", body), output.as_bytes()), None);
+    }
+
+    #[test]
+    fn validate_refnames() {
+        assert!(is_valid_refname("refs/heads/feature/x"));
+        assert!(!is_valid_refname("refs/heads/a..b"));
+        assert!(!is_valid_refname("refs/heads/.hidden"));
+        assert!(!is_valid_refname("refs/heads/main.lock"));
+        assert!(!is_valid_refname("refs/heads/a b"));
+        assert!(!is_valid_refname("refs/heads/\x07"));
+        assert!(!is_valid_refname("refs/heads/"));
+    }
+
+    #[test]
+    fn no_activity_if_get_request() {
+        let mut request = post("sample.git/info/refs", String::new());
+        request.required_method = "GET".to_string();
+        assert_eq!(parse_activity(&request, b"PACK\0\0\0\x02"), None);
+    }
+}
@@ -0,0 +1,139 @@
+use crate::command::share::activity::{is_object_id, RefUpdate, ZERO_ID};
+use crate::util::{colored_terminal_text, OutputErr};
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Fetches the refs pushed by a guest into `refs/remotes/gph/*` and prints the new commits.
+///
+/// `updates` must only contain ref updates that the owner's `git-receive-pack` accepted.
+pub async fn fetch_guest_push(updates: &[RefUpdate]) -> std::io::Result<()> {
+    git_fetch(None).await?;
+    for update in updates {
+        let Some(branch) = update.refname.strip_prefix("refs/heads/") else {
+            continue;
+        };
+        if update.new_id == ZERO_ID {
+            println!("{} gph/{branch}", colored_terminal_text(255, 165, 0, "Guest deleted"));
+            continue;
+        }
+        println!("{} gph/{branch}", colored_terminal_text(255, 165, 0, "Fetched guest commits into"));
+        for commit in git_log_new_commits(update).await.unwrap_or_default().lines() {
+            println!("    {commit}");
+        }
+    }
+    Ok(())
+}
+
+/// Returns the branches in the shared repository that differ from `refs/remotes/gph/*` of `working_dir`,
+/// or of the current directory if `None`.
+pub async fn unfetched_guest_branches(shared_repository: &Path, working_dir: Option<&Path>) -> std::io::Result<Vec<String>> {
+    let shared = branches(Some(shared_repository), "refs/heads").await?;
+    let fetched = branches(working_dir, "refs/remotes/gph").await?;
+    let mut unfetched = shared
+        .into_iter()
+        .filter(|(branch, object_id)| fetched.get(branch) != Some(object_id))
+        .map(|(branch, _)| branch)
+        .collect::<Vec<_>>();
+    unfetched.sort();
+    Ok(unfetched)
+}
+
+pub async fn git_fetch(working_dir: Option<&Path>) -> std::io::Result<()> {
+    let mut cmd = Command::new("git");
+    if let Some(working_dir) = working_dir {
+        cmd.current_dir(working_dir);
+    }
+    cmd
+        .arg("fetch")
+        .arg("--quiet")
+        .arg("gph")
+        .output()
+        .await?
+        .err_if_failed()?;
+    Ok(())
+}
+
+async fn git_log_new_commits(update: &RefUpdate) -> std::io::Result<String> {
+    let Some(args) = git_log_args(update) else {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid object id in guest push"));
+    };
+    let output = Command::new("git").args(args).output().await?.err_if_failed()?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The object ids come from the guest's request, so anything but full hex ids is refused
+/// and the revisions follow `--end-of-options` so that they can never be read as options.
+fn git_log_args(update: &RefUpdate) -> Option<Vec<String>> {
+    if !is_object_id(&update.old_id) || !is_object_id(&update.new_id) {
+        return None;
+    }
+    let mut args = ["log", "--oneline", "--no-decorate", "--max-count=20"].map(String::from).to_vec();
+    if update.old_id == ZERO_ID {
+        // Options cannot follow `--end-of-options`, so the local branches are excluded first
+        // and the second `--not` turns the new head back into a positive revision.
+        args.extend(["--not", "--branches", "--not", "--end-of-options", &update.new_id].map(String::from));
+    } else {
+        args.extend(["--end-of-options".to_string(), format!("{}..{}", update.old_id, update.new_id)]);
+    }
+    Some(args)
+}
+
+async fn branches(repository: Option<&Path>, prefix: &str) -> std::io::Result<HashMap<String, String>> {
+    let mut cmd = Command::new("git");
+    if let Some(repository) = repository {
+        cmd.current_dir(repository);
+    }
+    let output = cmd
+        .arg("for-each-ref")
+        .arg("--format=%(refname) %(objectname)")
+        .arg(prefix)
+        .output()
+        .await?
+        .err_if_failed()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter_map(|(refname, object_id)| {
+            let branch = refname.strip_prefix(prefix)?.strip_prefix('/')?;
+            Some((branch.to_string(), object_id.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::share::activity::{RefUpdate, ZERO_ID};
+    use crate::command::share::fetch::git_log_args;
+
+    const OLD_ID: &str = "1111111111111111111111111111111111111111";
+    const NEW_ID: &str = "2222222222222222222222222222222222222222";
+
+    fn update(old_id: &str, new_id: &str) -> RefUpdate {
+        RefUpdate {
+            old_id: old_id.to_string(),
+            new_id: new_id.to_string(),
+            refname: "refs/heads/main".to_string(),
+        }
+    }
+
+    #[test]
+    fn revisions_after_end_of_options() {
+        let args = git_log_args(&update(OLD_ID, NEW_ID)).unwrap();
+        assert_eq!(args[args.len() - 2], "--end-of-options");
+        assert_eq!(args[args.len() - 1], format!("{OLD_ID}..{NEW_ID}"));
+    }
+
+    #[test]
+    fn new_branch_excludes_local_branches() {
+        let args = git_log_args(&update(ZERO_ID, NEW_ID)).unwrap();
+        assert!(args.ends_with(&["--not", "--branches", "--not", "--end-of-options", NEW_ID].map(String::from)));
+    }
+
+    #[test]
+    fn none_if_invalid_object_id() {
+        assert_eq!(git_log_args(&update(OLD_ID, "--output=/tmp/pwned")), None);
+        assert_eq!(git_log_args(&update("HEAD", NEW_ID)), None);
+        assert_eq!(git_log_args(&update(OLD_ID, &NEW_ID[..39])), None);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::util::{colored_terminal_text, OutputErr};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::process::Command;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum BranchChange {
+    Updated {
+        branch: String,
+        object_id: String,
+    },
+    Deleted {
+        branch: String,
+    },
+}
+
+impl BranchChange {
+    fn branch(&self) -> &str {
+        match self {
+            Self::Updated { branch, .. } | Self::Deleted { branch } => branch,
+        }
+    }
+}
+
+/// Watches the local branches and publishes every branch whose head has moved or that was deleted to `gph`.
+///
+/// Never returns: failing to read the branches, for example while a rebase holds a lock, is retried on the next poll
+/// so that the share keeps running. Likewise a branch whose push failed keeps its last published head,
+/// so it is pushed again on the next poll.
+pub async fn follow_local_branches() {
+    let mut published = None;
+    loop {
+        match local_branches().await {
+            Ok(current) => match &mut published {
+                Some(published) => {
+                    for change in changed_branches(published, &current) {
+                        if publish(&change).await {
+                            record_published(published, change);
+                        }
+                    }
+                }
+                None => published = Some(current),
+            },
+            Err(e) => eprintln!("{}\n{e}", colored_terminal_text(255, 0, 0, "Failed to read local branches")),
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn changed_branches(previous: &HashMap<String, String>, current: &HashMap<String, String>) -> Vec<BranchChange> {
+    let mut changes = current
+        .iter()
+        .filter(|(branch, object_id)| previous.get(*branch) != Some(object_id))
+        .map(|(branch, object_id)| BranchChange::Updated {
+            branch: branch.clone(),
+            object_id: object_id.clone(),
+        })
+        .chain(previous
+            .keys()
+            .filter(|branch| !current.contains_key(*branch))
+            .map(|branch| BranchChange::Deleted { branch: branch.clone() }))
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| a.branch().cmp(b.branch()));
+    changes
+}
+
+fn record_published(published: &mut HashMap<String, String>, change: BranchChange) {
+    match change {
+        BranchChange::Updated { branch, object_id } => published.insert(branch, object_id),
+        BranchChange::Deleted { branch } => published.remove(&branch),
+    };
+}
+
+/// Pushes the change, returning whether it succeeded.
+///
+/// Updates are forced: the shared repository mirrors the owner's branches, which may be amended or rebased.
+async fn publish(change: &BranchChange) -> bool {
+    let (refspec, published) = match change {
+        BranchChange::Updated { branch, object_id } => (
+            format!("+refs/heads/{branch}:refs/heads/{branch}"),
+            format!("{} {branch} {}", colored_terminal_text(255, 255, 0, "Published"), &object_id[..object_id.len().min(7)]),
+        ),
+        BranchChange::Deleted { branch } => (
+            format!(":refs/heads/{branch}"),
+            format!("{} {branch}", colored_terminal_text(255, 255, 0, "Deleted")),
+        ),
+    };
+    match git_push(&refspec).await {
+        Ok(()) => {
+            println!("{published}");
+            true
+        }
+        Err(e) => {
+            eprintln!("{} {}; retrying\n{e}", colored_terminal_text(255, 0, 0, "Failed to publish"), change.branch());
+            false
+        }
+    }
+}
+
+async fn local_branches() -> std::io::Result<HashMap<String, String>> {
+    let output = Command::new("git")
+        .arg("for-each-ref")
+        .arg("--format=%(refname:short) %(objectname)")
+        .arg("refs/heads")
+        .output()
+        .await?
+        .err_if_failed()?;
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(branch, object_id)| (branch.to_string(), object_id.to_string()))
+        .collect())
+}
+
+async fn git_push(refspec: &str) -> std::io::Result<()> {
+    Command::new("git")
+        .arg("push")
+        .arg("--quiet")
+        .arg("gph")
+        .arg(refspec)
+        .output()
+        .await?
+        .err_if_failed()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command::share::follow::{changed_branches, record_published, BranchChange};
+    use std::collections::HashMap;
+
+    fn branches(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries.iter().map(|(branch, object_id)| (branch.to_string(), object_id.to_string())).collect()
+    }
+
+    #[test]
+    fn no_changes_if_same_heads() {
+        let previous = branches(&[("main", "1111111"), ("feature", "2222222")]);
+        assert!(changed_branches(&previous, &previous.clone()).is_empty());
+    }
+
+    #[test]
+    fn detect_moved_new_and_deleted_branches() {
+        let previous = branches(&[("main", "1111111"), ("old", "2222222"), ("stable", "3333333")]);
+        let current = branches(&[("main", "4444444"), ("new", "5555555"), ("stable", "3333333")]);
+        assert_eq!(changed_branches(&previous, &current), vec![
+            BranchChange::Updated { branch: "main".to_string(), object_id: "4444444".to_string() },
+            BranchChange::Updated { branch: "new".to_string(), object_id: "5555555".to_string() },
+            BranchChange::Deleted { branch: "old".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn failed_branch_changed_again_on_next_poll() {
+        let mut published = branches(&[("main", "1111111"), ("old", "2222222")]);
+        let current = branches(&[("main", "4444444"), ("new", "5555555")]);
+        let changes = changed_branches(&published, &current);
+        // Only the update of main was pushed successfully.
+        record_published(&mut published, changes[0].clone());
+        assert_eq!(changed_branches(&published, &current), vec![
+            BranchChange::Updated { branch: "new".to_string(), object_id: "5555555".to_string() },
+            BranchChange::Deleted { branch: "old".to_string() },
+        ]);
+
+        for change in changes.into_iter().skip(1) {
+            record_published(&mut published, change);
+        }
+        assert_eq!(published, current);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::command::list::print_shares;
+use crate::command::CommandExecutable;
+use crate::util::{colored_terminal_text, format_bytes, http_client, read_session_token, server_url};
+use async_trait::async_trait;
+use clap::Args;
+use gph_core::types::TransferUsage;
+
+#[derive(Debug, Clone, Args)]
+pub struct Status;
+
+#[async_trait]
+impl CommandExecutable for Status {
+    async fn execute(self) -> anyhow::Result<()> {
+        let Ok(session_token) = read_session_token() else {
+            println!("{}\nRun `gph auth` to authenticate.", colored_terminal_text(255, 0, 0, "Not authenticated."));
+            return Ok(());
+        };
+        let response = http_client()?
+            .get(server_url("/user_id"))
+            .bearer_auth(&session_token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            println!(
+                "{} {}\nRun `gph auth` to authenticate again.",
+                colored_terminal_text(255, 0, 0, "Not authenticated:"),
+                response.text().await?,
+            );
+            return Ok(());
+        }
+        println!("{} as user {}", colored_terminal_text(0, 255, 0, "Authenticated"), response.text().await?);
+        print_usage(&session_token).await;
+        print_shares(&session_token).await
+    }
+}
+
+/// Prints the transfer usage, or nothing if the server does not report it.
+async fn print_usage(session_token: &str) {
+    if let Ok(usage) = fetch_usage(session_token).await {
+        println!("Transferred today: {}", format_usage(usage.daily_bytes, usage.daily_quota));
+        println!("Transferred this month: {}", format_usage(usage.monthly_bytes, usage.monthly_quota));
+    }
+}
+
+async fn fetch_usage(session_token: &str) -> anyhow::Result<TransferUsage> {
+    let response = http_client()?
+        .get(server_url("/usage"))
+        .bearer_auth(session_token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.json().await?)
+}
+
+fn format_usage(bytes: u64, quota: Option<u64>) -> String {
+    match quota {
+        Some(quota) => format!("{} / {}", format_bytes(bytes), format_bytes(quota)),
+        None => format!("{} (unlimited)", format_bytes(bytes)),
+    }
+}
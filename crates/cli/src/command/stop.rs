@@ -0,0 +1,67 @@
+use crate::command::list::fetch_shares;
+use crate::command::share::{change_repository_extension, git_remote_remove, warn_unfetched_guest_branches};
+use crate::command::CommandExecutable;
+use crate::local_share::LocalShare;
+use crate::util::{colored_terminal_text, ensure_success, git_root, http_client, read_session_token, server_url};
+use anyhow::bail;
+use async_trait::async_trait;
+use clap::Args;
+
+#[derive(Debug, Clone, Args)]
+pub struct Stop {
+    /// Name of the shared repository
+    pub repository: String,
+}
+
+#[async_trait]
+impl CommandExecutable for Stop {
+    async fn execute(self) -> anyhow::Result<()> {
+        let repository = change_repository_extension(self.repository);
+        let local_share = LocalShare::load(&repository)?;
+        // A share recorded on this machine is cleaned up even if the session has expired or the server is unreachable,
+        // since its shell may be gone.
+        match close_server_share(&repository).await {
+            Ok(true) => {}
+            Ok(false) if local_share.is_some() => {}
+            Ok(false) => bail!("No share named `{repository}`"),
+            Err(e) if local_share.is_some() => {
+                eprintln!("{} {e}", colored_terminal_text(255, 165, 0, "Failed to close the share on the server:"));
+            }
+            Err(e) => return Err(e),
+        }
+
+        if let Some(local_share) = local_share {
+            let shared_repository = git_root()?.join(&repository);
+            let keep_shared_repository = shared_repository.exists()
+                && warn_unfetched_guest_branches(&shared_repository, &local_share.working_dir).await;
+            if let Err(e) = git_remote_remove(&local_share.working_dir).await {
+                eprintln!("Failed to remove git-remote `gph`: {e}");
+            }
+            if shared_repository.exists() && !keep_shared_repository {
+                std::fs::remove_dir_all(shared_repository)?;
+            }
+            LocalShare::remove(&repository)?;
+        }
+        println!("{} {repository}", colored_terminal_text(255, 255, 0, "Stopped"));
+        Ok(())
+    }
+}
+
+/// Closes the open share of `repository`, returning false if the server has none.
+async fn close_server_share(repository: &str) -> anyhow::Result<bool> {
+    let session_token = read_session_token()?;
+    let Some(share) = fetch_shares(&session_token)
+        .await?
+        .into_iter()
+        .find(|share| share.repository.as_deref() == Some(repository))
+    else {
+        return Ok(false);
+    };
+    let response = http_client()?
+        .delete(server_url(&format!("/shares/{}", share.id)))
+        .bearer_auth(&session_token)
+        .send()
+        .await?;
+    ensure_success(response).await?;
+    Ok(true)
+}
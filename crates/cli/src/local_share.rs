@@ -0,0 +1,64 @@
+use crate::util::app_dir;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A share started by `gph share` on this machine.
+///
+/// It is recorded so that `gph stop` can clean up even if the original shell is gone.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct LocalShare {
+    pub repository: String,
+    pub working_dir: PathBuf,
+    pub started_at: u64,
+}
+
+impl LocalShare {
+    pub fn new(repository: String, working_dir: PathBuf) -> Self {
+        Self {
+            repository,
+            working_dir,
+            started_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        std::fs::write(local_share_path(&self.repository)?, serde_json::to_string(self)?)
+    }
+
+    pub fn load(repository: &str) -> std::io::Result<Option<Self>> {
+        let path = local_share_path(repository)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn load_all() -> std::io::Result<Vec<Self>> {
+        let mut shares = Vec::new();
+        for entry in std::fs::read_dir(local_shares_dir()?)? {
+            let Ok(share) = serde_json::from_slice::<LocalShare>(&std::fs::read(entry?.path())?) else {
+                continue;
+            };
+            shares.push(share);
+        }
+        shares.sort_by(|a, b| a.repository.cmp(&b.repository));
+        Ok(shares)
+    }
+
+    pub fn remove(repository: &str) -> std::io::Result<()> {
+        std::fs::remove_file(local_share_path(repository)?)
+    }
+}
+
+fn local_share_path(repository: &str) -> std::io::Result<PathBuf> {
+    Ok(local_shares_dir()?.join(format!("{repository}.json")))
+}
+
+fn local_shares_dir() -> std::io::Result<PathBuf> {
+    let dir = app_dir().join("shares");
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
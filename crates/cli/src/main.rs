@@ -1,10 +1,16 @@
-use crate::command::{CliCommand, CommandExecutable};
+use crate::command::{Cli, CommandExecutable};
+use crate::profile::{CliConfig, Profile};
 use clap::Parser;
 
 mod command;
+mod local_share;
+mod profile;
 mod util;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    CliCommand::parse().execute().await
+    let cli = Cli::parse();
+    let config = CliConfig::load()?;
+    profile::init(Profile::resolve(&config, cli.profile.as_deref(), cli.server.as_deref())?);
+    cli.command.execute().await
 }
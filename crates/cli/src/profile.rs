@@ -0,0 +1,222 @@
+use crate::util::app_dir;
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+pub const DEFAULT_SERVER: &str = "https://git-phantom.com";
+const DEFAULT_PROFILE: &str = "default";
+
+static CURRENT: OnceLock<Profile> = OnceLock::new();
+
+/// The `gph` config file, `config.toml` in the user's config directory.
+///
+/// ```toml
+/// default_profile = "work"
+///
+/// [profiles.work]
+/// server = "https://gph.example.com"
+/// ```
+#[derive(Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CliConfig {
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileConfig {
+    pub server: String,
+}
+
+impl CliConfig {
+    pub fn path() -> Option<PathBuf> {
+        dirs_next::config_dir().map(|dir| dir.join("gph").join("config.toml"))
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(Self::default());
+        };
+        let text = std::fs::read_to_string(&path)?;
+        toml::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid config file {}: {e}", path.display()))
+    }
+}
+
+/// The server `gph` talks to and the name its session token is stored under.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    pub http_url: String,
+    pub ws_url: String,
+}
+
+impl Profile {
+    /// Picks the profile from `--profile`, then `--server`, then the config file's `default_profile`.
+    ///
+    /// A server that matches no profile gets one named after its host,
+    /// so that its session token does not overwrite the default one.
+    /// Giving both a profile and a server other than the profile's own is an error for the same reason.
+    pub fn resolve(config: &CliConfig, profile: Option<&str>, server: Option<&str>) -> anyhow::Result<Self> {
+        if let Some(name) = profile {
+            let configured = match config.profiles.get(name) {
+                Some(profile) => Some(profile.server.as_str()),
+                None if name == DEFAULT_PROFILE => Some(DEFAULT_SERVER),
+                None => None,
+            };
+            let server = match (server, configured) {
+                (Some(server), Some(configured))
+                    if server.trim_end_matches('/') != configured.trim_end_matches('/') =>
+                {
+                    anyhow::bail!("Profile `{name}` uses {configured}, not {server}; drop --profile or --server")
+                }
+                (Some(server), _) => server,
+                (None, Some(configured)) => configured,
+                (None, None) => anyhow::bail!("Profile `{name}` is not defined in the config file"),
+            };
+            return Self::new(name, server);
+        }
+        if let Some(server) = server {
+            let server = server.trim_end_matches('/');
+            if let Some((name, _)) = config
+                .profiles
+                .iter()
+                .find(|(_, profile)| profile.server.trim_end_matches('/') == server)
+            {
+                return Self::new(name, server);
+            }
+            if server == DEFAULT_SERVER {
+                return Self::new(DEFAULT_PROFILE, server);
+            }
+            let url = Url::parse(server)?;
+            let name = match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{host}_{port}"),
+                (Some(host), None) => host.to_string(),
+                (None, _) => anyhow::bail!("Invalid server url: {server}"),
+            };
+            return Self::new(&name, server);
+        }
+        match &config.default_profile {
+            Some(name) => Self::resolve(config, Some(name), None),
+            None => Self::resolve(config, Some(DEFAULT_PROFILE), None),
+        }
+    }
+
+    fn new(name: &str, server: &str) -> anyhow::Result<Self> {
+        let http_url = Url::parse(server)?;
+        let mut ws_url = http_url.clone();
+        let ws_scheme = match http_url.scheme() {
+            "https" => "wss",
+            "http" => "ws",
+            scheme => anyhow::bail!("Unsupported server scheme `{scheme}`; use http or https"),
+        };
+        ws_url
+            .set_scheme(ws_scheme)
+            .map_err(|_| anyhow::anyhow!("Invalid server url: {server}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            http_url: http_url.as_str().trim_end_matches('/').to_string(),
+            ws_url: ws_url.as_str().trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// The default profile keeps the session token path used before profiles existed.
+    pub fn session_token_path(&self) -> PathBuf {
+        if self.name == DEFAULT_PROFILE {
+            app_dir().join(".session")
+        } else {
+            let dir = app_dir().join("profiles").join(&self.name);
+            if !dir.exists() {
+                std::fs::create_dir_all(&dir).expect("Failed to create profile dir");
+            }
+            dir.join(".session")
+        }
+    }
+}
+
+/// Sets the profile for the rest of the process; called once from `main`.
+pub fn init(profile: Profile) {
+    let _ = CURRENT.set(profile);
+}
+
+pub fn current() -> &'static Profile {
+    CURRENT.get_or_init(|| Profile::new(DEFAULT_PROFILE, DEFAULT_SERVER).expect("Invalid default server"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::{CliConfig, Profile};
+
+    fn config() -> CliConfig {
+        toml::from_str(r#"
+        default_profile = "work"
+
+        [profiles.work]
+        server = "https://gph.example.com/"
+
+        [profiles.local]
+        server = "http://localhost:8080"
+        "#).unwrap()
+    }
+
+    #[test]
+    fn default_server_if_no_config() {
+        let profile = Profile::resolve(&CliConfig::default(), None, None).unwrap();
+        assert_eq!(profile.name, "default");
+        assert_eq!(profile.http_url, "https://git-phantom.com");
+        assert_eq!(profile.ws_url, "wss://git-phantom.com");
+    }
+
+    #[test]
+    fn default_profile_from_config() {
+        let profile = Profile::resolve(&config(), None, None).unwrap();
+        assert_eq!(profile.name, "work");
+        assert_eq!(profile.http_url, "https://gph.example.com");
+        assert_eq!(profile.ws_url, "wss://gph.example.com");
+    }
+
+    #[test]
+    fn named_profile() {
+        let profile = Profile::resolve(&config(), Some("local"), None).unwrap();
+        assert_eq!(profile.http_url, "http://localhost:8080");
+        assert_eq!(profile.ws_url, "ws://localhost:8080");
+    }
+
+    #[test]
+    fn server_matching_profile() {
+        let profile = Profile::resolve(&config(), None, Some("http://localhost:8080/")).unwrap();
+        assert_eq!(profile.name, "local");
+    }
+
+    #[test]
+    fn server_without_profile_named_after_host() {
+        let profile = Profile::resolve(&config(), None, Some("https://git.internal:8443")).unwrap();
+        assert_eq!(profile.name, "git.internal_8443");
+        assert_eq!(profile.ws_url, "wss://git.internal:8443");
+    }
+
+    #[test]
+    fn profile_with_its_own_server() {
+        let profile = Profile::resolve(&config(), Some("work"), Some("https://gph.example.com")).unwrap();
+        assert_eq!(profile.name, "work");
+        assert_eq!(profile.http_url, "https://gph.example.com");
+    }
+
+    #[test]
+    fn err_if_profile_and_other_server() {
+        assert!(Profile::resolve(&config(), Some("work"), Some("http://localhost:8080")).is_err());
+        assert!(Profile::resolve(&config(), Some("default"), Some("http://localhost:8080")).is_err());
+    }
+
+    #[test]
+    fn err_if_unknown_profile() {
+        assert!(Profile::resolve(&config(), Some("missing"), None).is_err());
+    }
+
+    #[test]
+    fn err_if_unsupported_scheme() {
+        assert!(Profile::resolve(&CliConfig::default(), None, Some("ftp://example.com")).is_err());
+    }
+}
@@ -1,16 +1,55 @@
+use crate::profile;
 use std::path::PathBuf;
 use std::process::Output;
 
-pub const HTTP_SERVER_ADDR: &str = "https://git-phantom.com";
-pub const WS_SERVER_ADDR: &str = "wss://git-phantom.com";
+/// The url of `path` on the server of the current profile.
+pub fn server_url(path: &str) -> String {
+    format!("{}{path}", profile::current().http_url)
+}
+
+/// The websocket url of `path` on the server of the current profile.
+pub fn ws_url(path: &str) -> String {
+    format!("{}{path}", profile::current().ws_url)
+}
 
 pub fn session_token_path() -> PathBuf {
-    app_dir().join(".session")
+    profile::current().session_token_path()
+}
+
+pub fn read_session_token() -> anyhow::Result<String> {
+    std::fs::read_to_string(session_token_path())
+        .map_err(|e| anyhow::anyhow!("Failed to read session token.\nIf you haven't authenticated yet, run `gph auth`\n{e:?}"))
+}
+
+/// Exchanges the saved session token for a new one, so that a copy of the old token stops working
+/// and the session does not hit the idle expiry. The absolute expiry is not extended.
+pub async fn refresh_session_token(session_token: &str) -> anyhow::Result<String> {
+    let response = http_client()?
+        .post(server_url("/session/refresh"))
+        .bearer_auth(session_token)
+        .send()
+        .await?;
+    let session_token = ensure_success(response).await?.text().await?;
+    std::fs::write(session_token_path(), &session_token)?;
+    Ok(session_token)
+}
+
+/// Turns an unsuccessful response into an error, suggesting `gph auth` if the session is no longer valid.
+pub async fn ensure_success(response: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let message = response.text().await?;
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        anyhow::bail!("{message}\nRun `gph auth` to authenticate again.");
+    }
+    anyhow::bail!("{message}")
 }
 
 pub fn app_dir() -> PathBuf {
     let dir = dirs_next::data_local_dir()
-        .or_else(|| dirs_next::data_dir())
+        .or_else(dirs_next::data_dir)
         .expect("Failed to read data local or data directory");
     let gph = dir.join("gph");
     if !gph.exists() {
@@ -19,10 +58,38 @@ pub fn app_dir() -> PathBuf {
     gph
 }
 
+pub fn git_root() -> std::io::Result<PathBuf> {
+    let dir = app_dir().join("git");
+    if !dir.exists() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+pub fn http_client() -> reqwest::Result<reqwest::Client> {
+    reqwest::ClientBuilder::new()
+        .use_rustls_tls()
+        .build()
+}
+
 pub fn colored_terminal_text(r: i32, g: i32, b: i32, text: &str) -> String {
     format!("\x1B[38;2;{};{};{}m{}\x1B[0m", r, g, b, text)
 }
 
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.;
+    let mut unit = 0;
+    while 1024. <= size && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
 pub trait OutputErr {
     fn err_if_failed(self) -> std::io::Result<Output>;
 }
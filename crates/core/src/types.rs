@@ -13,6 +13,8 @@ pub struct GitRequest {
     pub content_length: Option<String>,
     pub content_type: Option<String>,
     pub body: Vec<u8>,
+    #[serde(default)]
+    pub remote_addr: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -20,3 +22,108 @@ pub struct GitResponse {
     pub id: RequestId,
     pub output: Vec<u8>,
 }
+
+/// Options sent by the owner as query parameters when opening a share.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ShareOptions {
+    /// The name of the shared repository.
+    pub repository: Option<String>,
+
+    /// Whether guests are forbidden from pushing.
+    #[serde(default)]
+    pub readonly: bool,
+
+    /// The number of clones allowed before the share refuses further fetches.
+    pub max_clones: Option<u32>,
+}
+
+/// An open share, as returned by `GET /shares`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct ShareInfo {
+    pub id: Uuid,
+    pub repository: Option<String>,
+    pub readonly: bool,
+    pub opened_at: String,
+    pub max_clones: Option<u32>,
+    pub clone_count: u32,
+    pub guest_count: u32,
+    /// The url guests clone from, if the server knows its public url.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_url: Option<String>,
+}
+
+/// Returned by `POST /oauth2/device/code` to start a device-code login.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    /// Seconds until `device_code` expires.
+    pub expires_in: u64,
+    /// Minimum seconds to wait between polls of `POST /oauth2/device/token`.
+    #[serde(default = "default_device_interval")]
+    pub interval: u64,
+}
+
+fn default_device_interval() -> u64 {
+    5
+}
+
+/// The body of `POST /oauth2/device/token`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct DeviceTokenRequest {
+    pub device_code: String,
+    pub device_name: Option<String>,
+    /// The identity provider the device code was issued by; the server's default one if omitted.
+    #[serde(default)]
+    pub provider: Option<String>,
+}
+
+/// The result of polling `POST /oauth2/device/token`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DeviceTokenResponse {
+    /// The user has not entered the code yet.
+    Pending,
+    /// Polling too fast; the interval must be increased by 5 seconds.
+    SlowDown,
+    Complete {
+        session_token: String,
+    },
+}
+
+/// A signed-in device, as returned by `GET /sessions`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub device_name: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    /// Whether this is the session that made the request.
+    pub current: bool,
+}
+
+/// A guest request relayed to the owner, as returned by `GET /log`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct AccessLogEntry {
+    pub repository: String,
+    pub service: Option<String>,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub status: u16,
+    pub started_at: String,
+    pub finished_at: String,
+}
+
+/// Bytes relayed for the user's shares, as returned by `GET /usage`.
+///
+/// Days and months are in UTC. A quota of `None` means unlimited.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct TransferUsage {
+    pub daily_bytes: u64,
+    pub monthly_bytes: u64,
+    pub daily_quota: Option<u64>,
+    pub monthly_quota: Option<u64>,
+}
@@ -0,0 +1,2 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS max_clones INTEGER DEFAULT NULL;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS clone_count INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS access_log(
+    log_id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL,
+    repository TEXT NOT NULL,
+    service TEXT DEFAULT NULL,
+    remote_addr TEXT DEFAULT NULL,
+    user_agent TEXT DEFAULT NULL,
+    bytes_in BIGINT NOT NULL,
+    bytes_out BIGINT NOT NULL,
+    status INTEGER NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS access_log_user_id_started_at ON access_log(user_id, started_at DESC);
@@ -0,0 +1,18 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS room_id uuid NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS repository TEXT DEFAULT NULL;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS readonly BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS opened_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+CREATE OR REPLACE FUNCTION notify_room_closed() RETURNS trigger AS $notify_room_closed$
+BEGIN
+PERFORM PG_NOTIFY('room_closed', NEW.room_id::text);
+RETURN NEW;
+END;
+$notify_room_closed$
+LANGUAGE plpgsql;
+
+CREATE OR REPLACE TRIGGER notify_room_closed_trigger
+    AFTER UPDATE OF is_open ON rooms
+    FOR EACH ROW
+    WHEN (OLD.is_open=true AND NEW.is_open=false)
+    EXECUTE FUNCTION notify_room_closed();
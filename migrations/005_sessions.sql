@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS sessions(
+    session_id uuid NOT NULL PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id BIGINT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    session_token uuid NOT NULL UNIQUE DEFAULT gen_random_uuid(),
+    device_name TEXT DEFAULT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_index ON sessions(user_id);
+
+INSERT INTO sessions(user_id, session_token, created_at, last_used_at)
+SELECT user_id, session_token, COALESCE(created_at, CURRENT_TIMESTAMP), COALESCE(last_used_at, CURRENT_TIMESTAMP)
+FROM users;
+
+ALTER TABLE users DROP COLUMN IF EXISTS session_token;
+ALTER TABLE users DROP COLUMN IF EXISTS last_used_at;
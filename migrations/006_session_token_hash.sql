@@ -0,0 +1,5 @@
+-- Tokens are looked up by their keyed hash. Plaintext tokens of existing sessions are hashed
+-- by the server on startup, since the key is not known to the database, and then cleared.
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS token_hash BYTEA UNIQUE;
+ALTER TABLE sessions ALTER COLUMN session_token DROP NOT NULL;
+ALTER TABLE sessions ALTER COLUMN session_token DROP DEFAULT;
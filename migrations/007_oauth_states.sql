@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS oauth_states(
+    state TEXT NOT NULL PRIMARY KEY,
+    pkce_verifier TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
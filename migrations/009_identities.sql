@@ -0,0 +1,19 @@
+-- Users were keyed by their GitHub id. They now get an internal id, and each login
+-- identity (provider, subject) points at one. Existing ids are kept as GitHub identities.
+CREATE SEQUENCE IF NOT EXISTS users_user_id_seq OWNED BY users.user_id;
+SELECT setval('users_user_id_seq', COALESCE((SELECT max(user_id) FROM users), 0) + 1, false);
+ALTER TABLE users ALTER COLUMN user_id SET DEFAULT nextval('users_user_id_seq');
+
+CREATE TABLE IF NOT EXISTS identities(
+    provider TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id BIGINT NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY(provider, subject)
+);
+
+INSERT INTO identities(provider, subject, user_id)
+SELECT 'github', user_id::text, user_id FROM users
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE oauth_states ADD COLUMN IF NOT EXISTS provider TEXT NOT NULL DEFAULT 'github';
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS server_instances
+(
+    instance_id  UUID PRIMARY KEY,
+    started_at   TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    heartbeat_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The server instance holding the owner's websocket. Rooms opened before this column existed
+-- have no instance and are closed by the next instance that starts.
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS instance_id UUID DEFAULT NULL;
@@ -0,0 +1,8 @@
+-- Bytes relayed for each user's shares per UTC day; monthly usage is the sum over the month.
+CREATE TABLE IF NOT EXISTS transfer_usage
+(
+    user_id BIGINT NOT NULL,
+    day     DATE   NOT NULL,
+    bytes   BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
@@ -0,0 +1,6 @@
+-- Banned users keep their data but cannot authenticate.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS banned_at TIMESTAMPTZ DEFAULT NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS ban_reason TEXT DEFAULT NULL;
+
+-- Lets operators purge requests left behind when a server stopped before the owner answered.
+ALTER TABLE requests ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1,2 @@
+-- The nonce of the client that started the login, which must be sent again to finish it.
+ALTER TABLE oauth_states ADD COLUMN IF NOT EXISTS nonce TEXT DEFAULT NULL;
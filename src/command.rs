@@ -0,0 +1,101 @@
+use crate::config::ServerConfig;
+use crate::db::admin::AdminTable;
+use crate::middleware::user_id::UserId;
+use clap::{Parser, Subcommand};
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+use std::error::Error;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "git_phantom server")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the server; the default if no command is given
+    Serve,
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Manage rooms, users and requests in the database
+    #[command(subcommand)]
+    Admin(AdminCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AdminCommand {
+    /// List the rooms open on any server instance
+    ListRooms,
+    /// Close a room; its owner is disconnected
+    CloseRoom {
+        room_id: Uuid,
+    },
+    /// Ban a user from signing in and close their room
+    BanUser {
+        user_id: i64,
+        /// Recorded with the ban for other operators
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Lift a user's ban
+    UnbanUser {
+        user_id: i64,
+    },
+    /// Delete guest requests that no owner answered
+    PurgeRequests {
+        /// Only requests older than this; defaults to the relay response timeout
+        #[arg(long)]
+        older_than_secs: Option<u64>,
+    },
+}
+
+impl AdminCommand {
+    pub async fn execute(self, pool: &PgPool, config: &ServerConfig) -> Result<(), Box<dyn Error>> {
+        match self {
+            Self::ListRooms => {
+                let rooms = pool.select_all_open_rooms().await?;
+                if rooms.is_empty() {
+                    println!("No open rooms.");
+                }
+                for room in rooms {
+                    println!(
+                        "{} user:{} opened:{} clones:{} instance:{} {}",
+                        room.room_id,
+                        room.user_id,
+                        room.opened_at,
+                        room.clone_count,
+                        room.instance_id.map(|id| id.to_string()).unwrap_or_else(|| "-".to_string()),
+                        room.repository.as_deref().unwrap_or("-"),
+                    );
+                }
+            }
+            Self::CloseRoom { room_id } => {
+                if !pool.close_room_by_id(room_id).await? {
+                    return Err(format!("Room {room_id} is not open").into());
+                }
+                println!("Closed room {room_id}");
+            }
+            Self::BanUser { user_id, reason } => {
+                if !pool.ban_user(UserId(user_id), reason.as_deref()).await? {
+                    return Err(format!("User {user_id} not found").into());
+                }
+                println!("Banned user {user_id}");
+            }
+            Self::UnbanUser { user_id } => {
+                if !pool.unban_user(UserId(user_id)).await? {
+                    return Err(format!("User {user_id} not found").into());
+                }
+                println!("Unbanned user {user_id}");
+            }
+            Self::PurgeRequests { older_than_secs } => {
+                let older_than = older_than_secs.map(Duration::from_secs).unwrap_or(config.relay.response_timeout());
+                let purged = pool.purge_requests(older_than).await?;
+                println!("Purged {purged} requests");
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,516 @@
+use serde::Deserialize;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_CONFIG_PATH: &str = "gph-server.toml";
+
+const MIN_ADMIN_TOKEN_LEN: usize = 32;
+
+const MIN_SESSION_TOKEN_KEY_LEN: usize = 32;
+
+/// Server settings, read from a TOML file and then overridden by environment variables.
+///
+/// The file is `$GPH_CONFIG`, or `gph-server.toml` in the working directory if it exists.
+/// Every setting has a default, so neither the file nor any variable is required
+/// except for the database url.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub server: ListenConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub session: SessionConfig,
+    pub relay: RelayConfig,
+    pub log: LogConfig,
+    pub rate_limit: RateLimitConfig,
+    pub admin: AdminConfig,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    /// The url guests and the CLI reach the server at, such as `https://git-phantom.com`.
+    /// It can differ from the bind address when running behind a reverse proxy.
+    pub public_url: Option<String>,
+    /// How long shutdown waits for relayed requests to finish and owners to disconnect.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8080,
+            public_url: None,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+/// TLS is off by default, for running behind a TLS-terminating reverse proxy.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert_path: Option<PathBuf>,
+    pub key_path: Option<PathBuf>,
+    /// How often the certificate files are checked for changes. They are also reloaded on `SIGHUP`.
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_path: None,
+            key_path: None,
+            reload_interval_secs: 60,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn reload_interval(&self) -> Duration {
+        Duration::from_secs(self.reload_interval_secs.max(1))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: Option<String>,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            max_connections: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    /// The secret session tokens are hashed with; required to serve. Changing it signs every user out.
+    pub token_key: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelayConfig {
+    /// How long a guest request waits for the owner's response before failing with `504`.
+    pub response_timeout_secs: u64,
+    /// Larger request bodies, such as pushed packs, are rejected with `413`.
+    pub max_body_bytes: u64,
+    /// Bytes a user's shares may relay per UTC day, or unlimited if unset.
+    ///
+    /// Request bodies are charged before they are relayed and cannot exceed a quota,
+    /// but a response is charged after it is sent and may take the usage past it.
+    pub daily_quota_bytes: Option<u64>,
+    /// Bytes a user's shares may relay per UTC month, or unlimited if unset.
+    pub monthly_quota_bytes: Option<u64>,
+}
+
+impl Default for RelayConfig {
+    fn default() -> Self {
+        Self {
+            response_timeout_secs: 300,
+            max_body_bytes: 100 * 1024 * 1024,
+            daily_quota_bytes: Some(10 * 1024 * 1024 * 1024),
+            monthly_quota_bytes: Some(100 * 1024 * 1024 * 1024),
+        }
+    }
+}
+
+/// The `/admin` API and `/metrics` are each disabled unless their token is set.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer token required by every `/admin` request.
+    pub token: Option<String>,
+    /// Bearer token required by `/metrics`, kept apart so a scraper cannot call the admin API.
+    pub metrics_token: Option<String>,
+}
+
+/// Token-bucket limits; see `middleware::rate_limit`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Use the last `X-Forwarded-For` address, the one added by the proxy, as the client IP.
+    /// Enable only behind a single proxy that appends to it.
+    pub trust_forwarded_for: bool,
+    /// Every request, per client IP.
+    pub ip: RateLimit,
+    /// `/oauth2` requests, per client IP.
+    pub oauth: RateLimit,
+    /// Requests with a session token, per user.
+    pub user: RateLimit,
+    /// Git requests to a shared repository, per room.
+    pub room: RateLimit,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trust_forwarded_for: false,
+            ip: RateLimit { per_minute: 300, burst: 100 },
+            oauth: RateLimit { per_minute: 20, burst: 10 },
+            user: RateLimit { per_minute: 300, burst: 100 },
+            room: RateLimit { per_minute: 600, burst: 200 },
+        }
+    }
+}
+
+/// Allows `burst` requests at once, refilled at `per_minute`.
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Which events to log, in the `RUST_LOG` syntax such as `info` or `gph_server=debug`.
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            filter: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines for a terminal.
+    Pretty,
+    /// One JSON object per line for log collectors.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+impl ListenConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+impl RelayConfig {
+    pub fn response_timeout(&self) -> Duration {
+        Duration::from_secs(self.response_timeout_secs)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("Invalid config file {0}: {1}")]
+    Parse(PathBuf, toml::de::Error),
+
+    #[error("Invalid value of {0}: {1}")]
+    Env(&'static str, String),
+
+    #[error("{0}")]
+    Missing(&'static str),
+
+    #[error("{0}")]
+    Invalid(&'static str),
+}
+
+impl ServerConfig {
+    pub fn load() -> Result<ServerConfig, ConfigError> {
+        let path = std::env::var_os("GPH_CONFIG").map(PathBuf::from);
+        let mut config = match path {
+            Some(path) => ServerConfig::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => ServerConfig::from_file(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => ServerConfig::default(),
+        };
+        config.override_with(|key| std::env::var(key).ok().filter(|value| !value.is_empty()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<ServerConfig, ConfigError> {
+        let text = std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+    }
+
+    /// Applies the environment variables returned by `var` on top of the file.
+    ///
+    /// `DATABASE_URL`, `CERT_PEM` and `KEY_PEM` keep the names used before the config file existed.
+    fn override_with(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        fn parse<T: std::str::FromStr>(key: &'static str, value: String) -> Result<T, ConfigError> {
+            value.parse().map_err(|_| ConfigError::Env(key, value))
+        }
+        fn parse_quota(key: &'static str, value: String) -> Result<Option<u64>, ConfigError> {
+            if value == "unlimited" {
+                Ok(None)
+            } else {
+                parse(key, value).map(Some)
+            }
+        }
+        if let Some(value) = var("GPH_BIND_ADDRESS") {
+            self.server.bind_address = parse("GPH_BIND_ADDRESS", value)?;
+        }
+        if let Some(value) = var("GPH_PORT") {
+            self.server.port = parse("GPH_PORT", value)?;
+        }
+        if let Some(value) = var("GPH_PUBLIC_URL") {
+            self.server.public_url = Some(value);
+        }
+        if let Some(value) = var("GPH_SHUTDOWN_TIMEOUT_SECS") {
+            self.server.shutdown_timeout_secs = parse("GPH_SHUTDOWN_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("GPH_TLS") {
+            self.tls.enabled = parse("GPH_TLS", value)?;
+        }
+        if let Some(value) = var("CERT_PEM") {
+            self.tls.cert_path = Some(PathBuf::from(value));
+        }
+        if let Some(value) = var("KEY_PEM") {
+            self.tls.key_path = Some(PathBuf::from(value));
+        }
+        if let Some(value) = var("GPH_TLS_RELOAD_INTERVAL_SECS") {
+            self.tls.reload_interval_secs = parse("GPH_TLS_RELOAD_INTERVAL_SECS", value)?;
+        }
+        if let Some(value) = var("DATABASE_URL") {
+            self.database.url = Some(value);
+        }
+        if let Some(value) = var("SESSION_TOKEN_KEY") {
+            self.session.token_key = Some(value);
+        }
+        if let Some(value) = var("GPH_DATABASE_MAX_CONNECTIONS") {
+            self.database.max_connections = parse("GPH_DATABASE_MAX_CONNECTIONS", value)?;
+        }
+        if let Some(value) = var("GPH_RELAY_RESPONSE_TIMEOUT_SECS") {
+            self.relay.response_timeout_secs = parse("GPH_RELAY_RESPONSE_TIMEOUT_SECS", value)?;
+        }
+        if let Some(value) = var("GPH_RELAY_MAX_BODY_BYTES") {
+            self.relay.max_body_bytes = parse("GPH_RELAY_MAX_BODY_BYTES", value)?;
+        }
+        if let Some(value) = var("GPH_DAILY_QUOTA_BYTES") {
+            self.relay.daily_quota_bytes = parse_quota("GPH_DAILY_QUOTA_BYTES", value)?;
+        }
+        if let Some(value) = var("GPH_MONTHLY_QUOTA_BYTES") {
+            self.relay.monthly_quota_bytes = parse_quota("GPH_MONTHLY_QUOTA_BYTES", value)?;
+        }
+        if let Some(value) = var("GPH_RATE_LIMIT") {
+            self.rate_limit.enabled = parse("GPH_RATE_LIMIT", value)?;
+        }
+        if let Some(value) = var("GPH_ADMIN_TOKEN") {
+            self.admin.token = Some(value);
+        }
+        if let Some(value) = var("GPH_METRICS_TOKEN") {
+            self.admin.metrics_token = Some(value);
+        }
+        if let Some(value) = var("GPH_LOG_FORMAT") {
+            self.log.format = parse("GPH_LOG_FORMAT", value)?;
+        }
+        if let Some(value) = var("RUST_LOG") {
+            self.log.filter = value;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database.url.is_none() {
+            return Err(ConfigError::Missing("The database url is not set; set DATABASE_URL or database.url"));
+        }
+        if self.tls.enabled && (self.tls.cert_path.is_none() || self.tls.key_path.is_none()) {
+            return Err(ConfigError::Missing("TLS is enabled but the cert or key path is not set"));
+        }
+        if self.session.token_key.as_ref().is_some_and(|key| key.len() < MIN_SESSION_TOKEN_KEY_LEN) {
+            return Err(ConfigError::Invalid("The session token key must be at least 32 characters"));
+        }
+        if self.admin.token.as_ref().is_some_and(|token| token.len() < MIN_ADMIN_TOKEN_LEN) {
+            return Err(ConfigError::Invalid("The admin token must be at least 32 characters"));
+        }
+        if self.admin.metrics_token.as_ref().is_some_and(|token| token.len() < MIN_ADMIN_TOKEN_LEN) {
+            return Err(ConfigError::Invalid("The metrics token must be at least 32 characters"));
+        }
+        Ok(())
+    }
+
+    /// The session token key, which only `serve` needs.
+    pub fn session_token_key(&self) -> Result<&str, ConfigError> {
+        self.session
+            .token_key
+            .as_deref()
+            .ok_or(ConfigError::Missing("The session token key is not set; set SESSION_TOKEN_KEY or session.token_key"))
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.server.bind_address, self.server.port)
+    }
+
+    /// The url of a shared repository, if the public url is configured.
+    pub fn remote_url(&self, user_id: i64, repository: &str) -> Option<String> {
+        let public_url = self.server.public_url.as_deref()?.trim_end_matches('/');
+        Some(format!("{public_url}/git/{user_id}/{repository}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{ConfigError, LogFormat, RateLimit, RateLimitConfig, RelayConfig, ServerConfig};
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::path::PathBuf;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn defaults_if_empty_file() {
+        let config = toml::from_str::<ServerConfig>("").unwrap();
+        assert_eq!(config, ServerConfig::default());
+        assert_eq!(config.socket_addr().port(), 8080);
+        assert!(!config.tls.enabled);
+    }
+
+    #[test]
+    fn read_file() {
+        let config = toml::from_str::<ServerConfig>(r#"
+        [server]
+        bind_address = "127.0.0.1"
+        port = 3000
+        public_url = "https://gph.example.com/"
+
+        [tls]
+        enabled = true
+        cert_path = "/etc/gph/cert.pem"
+        key_path = "/etc/gph/key.pem"
+
+        [database]
+        max_connections = 50
+
+        [relay]
+        response_timeout_secs = 60
+        max_body_bytes = 1024
+
+        [log]
+        format = "json"
+
+        [rate_limit]
+        oauth = { per_minute = 5, burst = 2 }
+        "#).unwrap();
+        assert_eq!(config.server.bind_address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(config.server.port, 3000);
+        assert_eq!(config.tls.cert_path, Some(PathBuf::from("/etc/gph/cert.pem")));
+        assert_eq!(config.database.max_connections, 50);
+        assert_eq!(config.relay.response_timeout_secs, 60);
+        assert_eq!(config.relay.max_body_bytes, 1024);
+        assert_eq!(config.relay.daily_quota_bytes, RelayConfig::default().daily_quota_bytes);
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.log.filter, "info");
+        assert_eq!(config.rate_limit.oauth, RateLimit { per_minute: 5, burst: 2 });
+        assert_eq!(config.rate_limit.ip, RateLimitConfig::default().ip);
+        assert_eq!(config.remote_url(1, "repo.git").as_deref(), Some("https://gph.example.com/git/1/repo.git"));
+    }
+
+    #[test]
+    fn err_if_unknown_key() {
+        assert!(toml::from_str::<ServerConfig>("[server]\nprot = 3000").is_err());
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        let mut config = toml::from_str::<ServerConfig>("[server]\nport = 3000").unwrap();
+        config.override_with(env(&[
+            ("GPH_PORT", "4000"),
+            ("GPH_DAILY_QUOTA_BYTES", "unlimited"),
+            ("GPH_MONTHLY_QUOTA_BYTES", "2048"),
+            ("GPH_LOG_FORMAT", "json"),
+            ("RUST_LOG", "gph_server=debug"),
+            ("GPH_TLS", "true"),
+            ("CERT_PEM", "cert.pem"),
+            ("KEY_PEM", "key.pem"),
+            ("DATABASE_URL", "postgresql://localhost/gph"),
+        ])).unwrap();
+        assert_eq!(config.server.port, 4000);
+        assert_eq!(config.relay.daily_quota_bytes, None);
+        assert_eq!(config.relay.monthly_quota_bytes, Some(2048));
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.log.filter, "gph_server=debug");
+        assert!(config.tls.enabled);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn err_if_invalid_env() {
+        let mut config = ServerConfig::default();
+        let result = config.override_with(env(&[("GPH_PORT", "http")])).unwrap_err();
+        assert!(matches!(result, ConfigError::Env("GPH_PORT", _)));
+    }
+
+    #[test]
+    fn err_if_tls_without_cert() {
+        let mut config = ServerConfig::default();
+        config.override_with(env(&[("GPH_TLS", "true"), ("DATABASE_URL", "postgresql://localhost/gph")])).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Missing(_))));
+    }
+
+    #[test]
+    fn err_if_admin_token_too_short() {
+        let mut config = ServerConfig::default();
+        config.override_with(env(&[("GPH_ADMIN_TOKEN", "secret"), ("DATABASE_URL", "postgresql://localhost/gph")])).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn err_if_session_token_key_too_short() {
+        let mut config = ServerConfig::default();
+        config.override_with(env(&[("SESSION_TOKEN_KEY", "secret"), ("DATABASE_URL", "postgresql://localhost/gph")])).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn err_if_session_token_key_missing() {
+        let config = ServerConfig::default();
+        assert!(matches!(config.session_token_key(), Err(ConfigError::Missing(_))));
+
+        let mut config = ServerConfig::default();
+        config.override_with(env(&[("SESSION_TOKEN_KEY", "session-key-0123456789abcdef01234")])).unwrap();
+        assert_eq!(config.session_token_key().unwrap(), "session-key-0123456789abcdef01234");
+    }
+
+    #[test]
+    fn err_if_metrics_token_too_short() {
+        let mut config = ServerConfig::default();
+        config.override_with(env(&[("GPH_METRICS_TOKEN", "secret"), ("DATABASE_URL", "postgresql://localhost/gph")])).unwrap();
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))));
+    }
+}
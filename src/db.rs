@@ -1,29 +1,51 @@
 pub mod users;
+pub mod admin;
+pub mod sessions;
 pub mod channel;
 pub mod rooms;
+pub mod access_log;
+pub mod oauth_states;
+pub mod instances;
+pub mod transfer_usage;
 
 
 #[cfg(test)]
 pub(crate) mod test {
     use crate::middleware::session_token::SessionToken;
     use crate::middleware::user_id::UserId;
+    use crate::state::SessionKey;
     use sqlx::types::Uuid;
     use sqlx::{Executor, PgPool};
 
-    pub const SESSION1: SessionToken = SessionToken(Uuid::from_u128(0xa1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8u128));
+    pub const SESSION1: &str = "gph_a1a2a3a4b1b2c1c2d1d2d3d4d5d6d7d8";
+
+    pub const INSTANCE1: Uuid = Uuid::from_u128(1);
 
     pub trait DBInit {
         async fn init(&self);
+
+        async fn insert_user(&self, user_id: UserId);
     }
     impl DBInit for PgPool {
         async fn init(&self) {
             let sql = include_str!("../migrations/000_init.sql");
             self.execute(sql).await.unwrap();
+            self.insert_user(UserId::USER1).await;
             sqlx::query(r#"
-            INSERT INTO users(user_id, session_token) VALUES($1, $2)
+            INSERT INTO sessions(user_id, token_hash) VALUES($1, $2)
             "#)
                 .bind(UserId::USER1.0)
-                .bind(SESSION1.0)
+                .bind(SessionKey::test().hash(&SessionToken(SESSION1.to_string())).0)
+                .execute(self)
+                .await
+                .unwrap();
+        }
+
+        async fn insert_user(&self, user_id: UserId) {
+            sqlx::query(r#"
+            INSERT INTO users(user_id) VALUES($1)
+            "#)
+                .bind(user_id.0)
                 .execute(self)
                 .await
                 .unwrap();
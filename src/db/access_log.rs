@@ -0,0 +1,133 @@
+use crate::error::ServerResult;
+use crate::middleware::user_id::UserId;
+use gph_core::types::AccessLogEntry;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::{PgPool, Row};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NewAccessLog {
+    pub user_id: UserId,
+    pub repository: String,
+    pub service: Option<String>,
+    pub remote_addr: Option<String>,
+    pub user_agent: Option<String>,
+    pub bytes_in: i64,
+    pub bytes_out: i64,
+    pub status: u16,
+    pub started_at: OffsetDateTime,
+}
+
+pub trait AccessLogTable {
+    async fn insert_access_log(&self, log: &NewAccessLog) -> ServerResult;
+
+    async fn select_access_log(&self, user_id: UserId, limit: i64) -> ServerResult<Vec<AccessLogEntry>>;
+}
+
+impl AccessLogTable for PgPool {
+    async fn insert_access_log(&self, log: &NewAccessLog) -> ServerResult {
+        sqlx::query(r#"
+        INSERT INTO access_log(user_id, repository, service, remote_addr, user_agent, bytes_in, bytes_out, status, started_at)
+        VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#)
+            .bind(log.user_id.0)
+            .bind(&log.repository)
+            .bind(&log.service)
+            .bind(&log.remote_addr)
+            .bind(&log.user_agent)
+            .bind(log.bytes_in)
+            .bind(log.bytes_out)
+            .bind(log.status as i32)
+            .bind(log.started_at)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn select_access_log(&self, user_id: UserId, limit: i64) -> ServerResult<Vec<AccessLogEntry>> {
+        let rows = sqlx::query(r#"
+        SELECT
+            repository,
+            service,
+            remote_addr,
+            user_agent,
+            bytes_in,
+            bytes_out,
+            status,
+            to_char(started_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+            to_char(finished_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"')
+        FROM access_log WHERE user_id=$1
+        ORDER BY started_at DESC, log_id DESC
+        LIMIT $2
+        "#)
+            .bind(user_id.0)
+            .bind(limit)
+            .fetch_all(self)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| AccessLogEntry {
+                repository: row.get(0),
+                service: row.get(1),
+                remote_addr: row.get(2),
+                user_agent: row.get(3),
+                bytes_in: row.get(4),
+                bytes_out: row.get(5),
+                status: row.get::<i32, _>(6) as u16,
+                started_at: row.get(7),
+                finished_at: row.get(8),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::db::access_log::{AccessLogTable, NewAccessLog};
+    use crate::middleware::user_id::UserId;
+    use crate::test::TestResult;
+    use sqlx::types::time::OffsetDateTime;
+    use sqlx::PgPool;
+
+    pub fn access_log(user_id: UserId) -> NewAccessLog {
+        NewAccessLog {
+            user_id,
+            repository: "sample.git".to_string(),
+            service: Some("git-upload-pack".to_string()),
+            remote_addr: Some("127.0.0.1".to_string()),
+            user_agent: Some("git/2.43.0".to_string()),
+            bytes_in: 10,
+            bytes_out: 20,
+            status: 200,
+            started_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    #[sqlx::test]
+    async fn ok_insert_access_log(pool: PgPool) -> TestResult {
+        pool.insert_access_log(&access_log(UserId::USER1)).await?;
+        let logs = pool.select_access_log(UserId::USER1, 10).await?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].repository, "sample.git");
+        assert_eq!(logs[0].service.as_deref(), Some("git-upload-pack"));
+        assert_eq!(logs[0].status, 200);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn select_only_own_access_log(pool: PgPool) -> TestResult {
+        pool.insert_access_log(&access_log(UserId(2))).await?;
+        let logs = pool.select_access_log(UserId::USER1, 10).await?;
+        assert!(logs.is_empty());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn select_access_log_within_limit(pool: PgPool) -> TestResult {
+        for _ in 0..3 {
+            pool.insert_access_log(&access_log(UserId::USER1)).await?;
+        }
+        let logs = pool.select_access_log(UserId::USER1, 2).await?;
+        assert_eq!(logs.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use crate::error::ServerResult;
+use crate::middleware::user_id::UserId;
+use serde::Serialize;
+use sqlx::types::Uuid;
+use sqlx::{PgPool, Row};
+use std::time::Duration;
+
+/// A room as listed to operators, across all users and server instances.
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct OpenRoom {
+    pub room_id: Uuid,
+    pub user_id: i64,
+    pub repository: Option<String>,
+    pub opened_at: String,
+    pub clone_count: i32,
+    pub instance_id: Option<Uuid>,
+}
+
+/// Operations for `gph-server admin` and the `/admin` API, which act on any user.
+pub trait AdminTable {
+    /// Lists the rooms open on any server instance, oldest first.
+    async fn select_all_open_rooms(&self) -> ServerResult<Vec<OpenRoom>>;
+
+    /// Closes a room whoever owns it; the owner's websocket is sent a close frame.
+    async fn close_room_by_id(&self, room_id: Uuid) -> ServerResult<bool>;
+
+    /// Bans the user and closes their room. Returns false if the user does not exist.
+    async fn ban_user(&self, user_id: UserId, reason: Option<&str>) -> ServerResult<bool>;
+
+    async fn unban_user(&self, user_id: UserId) -> ServerResult<bool>;
+
+    /// Deletes guest requests older than `older_than`, which no owner is going to answer,
+    /// and returns how many were deleted.
+    async fn purge_requests(&self, older_than: Duration) -> ServerResult<u64>;
+}
+
+impl AdminTable for PgPool {
+    async fn select_all_open_rooms(&self) -> ServerResult<Vec<OpenRoom>> {
+        let rows = sqlx::query(r#"
+        SELECT
+            room_id,
+            user_id,
+            repository,
+            to_char(opened_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+            clone_count,
+            instance_id
+        FROM rooms WHERE is_open=true ORDER BY opened_at
+        "#)
+            .fetch_all(self)
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| OpenRoom {
+                room_id: row.get(0),
+                user_id: row.get(1),
+                repository: row.get(2),
+                opened_at: row.get(3),
+                clone_count: row.get(4),
+                instance_id: row.get(5),
+            })
+            .collect())
+    }
+
+    async fn close_room_by_id(&self, room_id: Uuid) -> ServerResult<bool> {
+        let result = sqlx::query("UPDATE rooms SET is_open=false WHERE room_id=$1 AND is_open=true")
+            .bind(room_id)
+            .execute(self)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn ban_user(&self, user_id: UserId, reason: Option<&str>) -> ServerResult<bool> {
+        let mut tx = self.begin().await?;
+        let result = sqlx::query("UPDATE users SET banned_at=CURRENT_TIMESTAMP, ban_reason=$2 WHERE user_id=$1")
+            .bind(user_id.0)
+            .bind(reason)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE rooms SET is_open=false WHERE user_id=$1 AND is_open=true")
+            .bind(user_id.0)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn unban_user(&self, user_id: UserId) -> ServerResult<bool> {
+        let result = sqlx::query("UPDATE users SET banned_at=NULL, ban_reason=NULL WHERE user_id=$1")
+            .bind(user_id.0)
+            .execute(self)
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn purge_requests(&self, older_than: Duration) -> ServerResult<u64> {
+        let result = sqlx::query("DELETE FROM requests WHERE created_at + make_interval(secs => $1) < CURRENT_TIMESTAMP")
+            .bind(older_than.as_secs_f64())
+            .execute(self)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::admin::AdminTable;
+    use crate::db::channel::guest::new_request;
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::{DBInit, INSTANCE1};
+    use crate::middleware::user_id::UserId;
+    use crate::test::TestResult;
+    use gph_core::types::ShareOptions;
+    use sqlx::types::Uuid;
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    #[sqlx::test]
+    async fn list_and_close_open_rooms(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let rooms = pool.select_all_open_rooms().await?;
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].room_id, room_id);
+        assert_eq!(rooms[0].user_id, UserId::USER1.0);
+        assert_eq!(rooms[0].instance_id, Some(INSTANCE1));
+
+        assert!(pool.close_room_by_id(room_id).await?);
+        assert!(!pool.close_room_by_id(room_id).await?);
+        assert!(pool.select_all_open_rooms().await?.is_empty());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_closed_if_unknown_room(pool: PgPool) -> TestResult {
+        assert!(!pool.close_room_by_id(Uuid::new_v4()).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ban_closes_room(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert!(pool.ban_user(UserId::USER1, Some("abuse")).await?);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        assert!(pool.unban_user(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_banned_if_unknown_user(pool: PgPool) -> TestResult {
+        assert!(!pool.ban_user(UserId(404), None).await?);
+        assert!(!pool.unban_user(UserId(404)).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn purge_old_requests(pool: PgPool) -> TestResult {
+        let old = new_request(&pool, UserId::USER1, b"old").await?;
+        new_request(&pool, UserId::USER1, b"new").await?;
+        sqlx::query("UPDATE requests SET created_at=CURRENT_TIMESTAMP - interval '2 hours' WHERE request_id=$1")
+            .bind(old.0)
+            .execute(&pool)
+            .await?;
+
+        assert_eq!(pool.purge_requests(Duration::from_secs(3600)).await?, 1);
+        let remaining: i64 = sqlx::query_scalar("SELECT count(*) FROM requests").fetch_one(&pool).await?;
+        assert_eq!(remaining, 1);
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ pub struct RequestNotify {
     pub query_string: Option<String>,
     pub content_length: Option<String>,
     pub content_type: Option<String>,
+    pub remote_addr: Option<String>,
 }
 
 pub fn convert_to_git_request(notify: RequestNotify, request_body: Vec<u8>) -> GitRequest {
@@ -24,6 +25,7 @@ pub fn convert_to_git_request(notify: RequestNotify, request_body: Vec<u8>) -> G
         content_length: notify.content_length,
         content_type: notify.content_type,
         body: request_body,
+        remote_addr: notify.remote_addr,
     }
 }
 
@@ -1,5 +1,6 @@
 use crate::db::channel::RequestNotify;
 use crate::error::ServerResult;
+use crate::metrics::METRICS;
 use crate::middleware::user_id::UserId;
 use async_stream::__private::AsyncStream;
 use gph_core::types::RequestId;
@@ -14,7 +15,11 @@ pub async fn listen(pool: PgPool, request_id: RequestId) -> ServerResult<AsyncSt
     let request_id_string = request_id.0.to_string();
 
     Ok(async_stream::stream! {
-        while let Ok(notify) = listener.recv().await {
+        while let Ok(notify) = listener.try_recv().await {
+            let Some(notify) = notify else {
+                METRICS.record_listener_reconnect();
+                continue;
+            };
             if request_id_string != notify.payload() {
                 continue;
             }
@@ -36,16 +41,24 @@ pub async fn request_to_owner(pool: &PgPool, request: &RequestNotify) -> ServerR
     Ok(())
 }
 
+#[cfg(test)]
 pub(crate) async fn new_request(pool: &PgPool, user_id: UserId, request_body: &[u8]) -> ServerResult<RequestId> {
-    let request_id = sqlx::query(r#"
-    INSERT INTO requests(user_id, request_body) VALUES($1, $2) RETURNING request_id
+    let request_id = RequestId(sqlx::types::Uuid::new_v4());
+    insert_request(pool, request_id, user_id, request_body).await?;
+    Ok(request_id)
+}
+
+/// Stores a guest request under an id chosen by the caller, so that it can be logged before the insert.
+pub(crate) async fn insert_request(pool: &PgPool, request_id: RequestId, user_id: UserId, request_body: &[u8]) -> ServerResult {
+    sqlx::query(r#"
+    INSERT INTO requests(request_id, user_id, request_body) VALUES($1, $2, $3)
     "#)
+        .bind(request_id.0)
         .bind(user_id.0)
         .bind(request_body)
-        .fetch_one(pool)
-        .await?
-        .get(0);
-    Ok(RequestId(request_id))
+        .execute(pool)
+        .await?;
+    Ok(())
 }
 
 pub(crate) async fn pop_response(pool: &PgPool, request_id: &RequestId) -> ServerResult<Vec<u8>> {
@@ -1,9 +1,11 @@
 use crate::db::channel::{convert_to_git_request, RequestNotify};
 use crate::error::ServerResult;
+use crate::metrics::METRICS;
 use crate::middleware::user_id::UserId;
 use async_stream::__private::AsyncStream;
 use gph_core::types::{GitRequest, RequestId};
 use sqlx::postgres::PgListener;
+use sqlx::types::Uuid;
 use sqlx::{Executor, PgPool, Postgres, Row};
 use std::future::Future;
 
@@ -12,7 +14,11 @@ pub async fn listen(pool: PgPool, user_id: UserId) -> ServerResult<AsyncStream<G
     listener.listen("owner").await?;
 
     Ok(async_stream::stream! {
-        while let Ok(notify) = listener.recv().await {
+        while let Ok(notify) = listener.try_recv().await {
+            let Some(notify) = notify else {
+                METRICS.record_listener_reconnect();
+                continue;
+            };
             let Ok(meta) = serde_json::from_str::<RequestNotify>(notify.payload()) else {
                 continue;
             };
@@ -27,6 +33,24 @@ pub async fn listen(pool: PgPool, user_id: UserId) -> ServerResult<AsyncStream<G
     })
 }
 
+/// Yields the ids of rooms as they are closed.
+pub async fn listen_room_closed(pool: &PgPool) -> ServerResult<AsyncStream<Uuid, impl Future<Output=()> + Send>> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen("room_closed").await?;
+
+    Ok(async_stream::stream! {
+        while let Ok(notify) = listener.try_recv().await {
+            let Some(notify) = notify else {
+                METRICS.record_listener_reconnect();
+                continue;
+            };
+            if let Ok(room_id) = Uuid::parse_str(notify.payload()) {
+                yield room_id;
+            }
+        }
+    })
+}
+
 pub async fn response<'c, E>(pool: E, request_id: &RequestId, response: &[u8]) -> ServerResult
 where
     E: Executor<'c, Database=Postgres>,
@@ -55,12 +79,17 @@ async fn select_request_body(pool: &PgPool, request_id: RequestId) -> ServerResu
 #[cfg(test)]
 mod tests {
     use crate::db::channel::guest::{new_request, pop_response};
-    use crate::db::channel::owner::response;
+    use crate::db::channel::owner::{listen_room_closed, response};
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::INSTANCE1;
     use crate::error::ServerError;
     use crate::middleware::user_id::UserId;
     use crate::test::TestResult;
+    use futures_util::{pin_mut, StreamExt};
+    use gph_core::types::ShareOptions;
     use sqlx::postgres::PgListener;
     use sqlx::{PgPool, Row};
+    use std::time::Duration;
 
     #[sqlx::test]
     async fn ok_response(pool: PgPool) -> TestResult {
@@ -106,6 +135,23 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn recv_room_closed(pool: PgPool) -> TestResult {
+        let stream = listen_room_closed(&pool).await?;
+        pin_mut!(stream);
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.close_room(UserId::USER1, room_id).await?;
+        tokio::select! {
+            actual = stream.next() => {
+                assert_eq!(actual, Some(room_id));
+            }
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                panic!("time out");
+            }
+        }
+        Ok(())
+    }
+
     async fn requests_count(pool: &PgPool) -> TestResult<i64> {
         let count: i64 = sqlx::query("SELECT count(*) FROM requests")
             .fetch_one(pool)
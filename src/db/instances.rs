@@ -0,0 +1,121 @@
+use crate::error::ServerResult;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Server processes sharing the database, so that rooms held by a crashed one can be closed.
+pub trait InstancesTable {
+    /// Records that `instance_id` is alive; called at startup and then periodically.
+    async fn heartbeat_instance(&self, instance_id: Uuid) -> ServerResult;
+
+    /// Closes the rooms of `instance_id` and forgets it, on graceful shutdown.
+    async fn remove_instance(&self, instance_id: Uuid) -> ServerResult<u64>;
+
+    /// Closes open rooms whose instance has not sent a heartbeat within `timeout`,
+    /// and returns how many were closed.
+    async fn close_stale_rooms(&self, timeout: Duration) -> ServerResult<u64>;
+}
+
+impl InstancesTable for PgPool {
+    async fn heartbeat_instance(&self, instance_id: Uuid) -> ServerResult {
+        sqlx::query(r#"
+        INSERT INTO server_instances(instance_id) VALUES($1)
+        ON CONFLICT(instance_id) DO UPDATE SET heartbeat_at=CURRENT_TIMESTAMP
+        "#)
+            .bind(instance_id)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_instance(&self, instance_id: Uuid) -> ServerResult<u64> {
+        let mut tx = self.begin().await?;
+        let result = sqlx::query("UPDATE rooms SET is_open=false WHERE instance_id=$1 AND is_open=true")
+            .bind(instance_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM server_instances WHERE instance_id=$1")
+            .bind(instance_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn close_stale_rooms(&self, timeout: Duration) -> ServerResult<u64> {
+        let mut tx = self.begin().await?;
+        sqlx::query("DELETE FROM server_instances WHERE heartbeat_at + make_interval(secs => $1) < CURRENT_TIMESTAMP")
+            .bind(timeout.as_secs_f64())
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query(r#"
+        UPDATE rooms SET is_open=false
+        WHERE is_open=true AND (instance_id IS NULL OR instance_id NOT IN (SELECT instance_id FROM server_instances))
+        "#)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::instances::InstancesTable;
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::{DBInit, INSTANCE1};
+    use crate::middleware::user_id::UserId;
+    use crate::test::TestResult;
+    use gph_core::types::ShareOptions;
+    use sqlx::types::Uuid;
+    use sqlx::PgPool;
+    use std::time::Duration;
+
+    #[sqlx::test]
+    async fn keep_rooms_of_live_instance(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.heartbeat_instance(INSTANCE1).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert_eq!(pool.close_stale_rooms(Duration::from_secs(60)).await?, 0);
+        assert!(pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn close_rooms_of_stale_instance(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.heartbeat_instance(INSTANCE1).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        sqlx::query("UPDATE server_instances SET heartbeat_at=CURRENT_TIMESTAMP - interval '2 minutes'")
+            .execute(&pool)
+            .await?;
+        assert_eq!(pool.close_stale_rooms(Duration::from_secs(60)).await?, 1);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn close_rooms_of_unknown_instance(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.open_room(UserId::USER1, Uuid::new_v4(), &ShareOptions::default()).await?;
+        assert_eq!(pool.close_stale_rooms(Duration::from_secs(60)).await?, 1);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn close_rooms_on_remove_instance(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.insert_user(UserId(2)).await;
+        let other = Uuid::new_v4();
+        pool.heartbeat_instance(INSTANCE1).await?;
+        pool.heartbeat_instance(other).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.open_room(UserId(2), other, &ShareOptions::default()).await?;
+
+        assert_eq!(pool.remove_instance(INSTANCE1).await?, 1);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        assert!(pool.is_open_room(UserId(2)).await?);
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::error::{ServerError, ServerResult};
+use sqlx::{PgPool, Row};
+
+/// How long a login attempt may take between `/oauth2/auth` and `/oauth2/register`.
+const OAUTH_STATE_LIFETIME_SECS: f64 = 10. * 60.;
+
+/// A login attempt started by `/oauth2/auth`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OAuthState {
+    /// The name of the identity provider the user is logging in with.
+    pub provider: String,
+    pub pkce_verifier: String,
+    /// The CLI's loopback callback, which must be sent again when exchanging the code.
+    pub redirect_uri: Option<String>,
+    /// Generated by the client that started the login, which must send it again to finish it,
+    /// so that a code and state obtained by someone else cannot sign it in.
+    pub nonce: Option<String>,
+}
+
+pub trait OAuthStatesTable {
+    async fn insert_oauth_state(&self, state: &str, oauth_state: &OAuthState) -> ServerResult;
+
+    /// Consumes the login attempt identified by `state`.
+    ///
+    /// Each state can be used only once.
+    async fn take_oauth_state(&self, state: &str) -> ServerResult<OAuthState>;
+}
+
+impl OAuthStatesTable for PgPool {
+    async fn insert_oauth_state(&self, state: &str, oauth_state: &OAuthState) -> ServerResult {
+        sqlx::query("DELETE FROM oauth_states WHERE created_at + make_interval(secs => $1) < CURRENT_TIMESTAMP")
+            .bind(OAUTH_STATE_LIFETIME_SECS)
+            .execute(self)
+            .await?;
+        sqlx::query("INSERT INTO oauth_states(state, provider, pkce_verifier, redirect_uri, nonce) VALUES($1, $2, $3, $4, $5)")
+            .bind(state)
+            .bind(&oauth_state.provider)
+            .bind(&oauth_state.pkce_verifier)
+            .bind(&oauth_state.redirect_uri)
+            .bind(&oauth_state.nonce)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn take_oauth_state(&self, state: &str) -> ServerResult<OAuthState> {
+        let row = sqlx::query(r#"
+        DELETE FROM oauth_states
+        WHERE state=$1 AND CURRENT_TIMESTAMP <= created_at + make_interval(secs => $2)
+        RETURNING provider, pkce_verifier, redirect_uri, nonce
+        "#)
+            .bind(state)
+            .bind(OAUTH_STATE_LIFETIME_SECS)
+            .fetch_optional(self)
+            .await?
+            .ok_or(ServerError::InvalidOAuthState)?;
+        Ok(OAuthState {
+            provider: row.get(0),
+            pkce_verifier: row.get(1),
+            redirect_uri: row.get(2),
+            nonce: row.get(3),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::oauth_states::{OAuthState, OAuthStatesTable};
+    use crate::error::ServerError;
+    use crate::test::TestResult;
+    use sqlx::PgPool;
+
+    fn oauth_state() -> OAuthState {
+        OAuthState {
+            provider: "gitlab".to_string(),
+            pkce_verifier: "verifier".to_string(),
+            redirect_uri: Some("http://127.0.0.1:1234/oauth2/callback".to_string()),
+            nonce: Some("nonce".to_string()),
+        }
+    }
+
+    #[sqlx::test]
+    async fn ok_take_oauth_state(pool: PgPool) -> TestResult {
+        pool.insert_oauth_state("state", &oauth_state()).await?;
+        assert_eq!(pool.take_oauth_state("state").await?, oauth_state());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_take_oauth_state_twice(pool: PgPool) -> TestResult {
+        pool.insert_oauth_state("state", &oauth_state()).await?;
+        pool.take_oauth_state("state").await?;
+        let result = pool.take_oauth_state("state").await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidOAuthState));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_take_expired_oauth_state(pool: PgPool) -> TestResult {
+        pool.insert_oauth_state("state", &oauth_state()).await?;
+        sqlx::query("UPDATE oauth_states SET created_at=CURRENT_TIMESTAMP - interval '1 hour'")
+            .execute(&pool)
+            .await?;
+        let result = pool.take_oauth_state("state").await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidOAuthState));
+        Ok(())
+    }
+}
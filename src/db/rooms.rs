@@ -1,24 +1,105 @@
 use crate::error::{ServerError, ServerResult};
 use crate::middleware::user_id::UserId;
+use gph_core::types::{ShareInfo, ShareOptions};
+use sqlx::postgres::PgRow;
+use sqlx::types::Uuid;
 use sqlx::{PgPool, Row};
 
+const SELECT_SHARE_INFO: &str = r#"
+SELECT
+    room_id,
+    repository,
+    readonly,
+    to_char(opened_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+    max_clones,
+    clone_count,
+    (
+        SELECT count(DISTINCT remote_addr) FROM access_log
+        WHERE access_log.user_id=rooms.user_id AND rooms.opened_at <= access_log.started_at
+    )
+FROM rooms
+"#;
+
 pub trait RoomsTable {
-    async fn update_room_status(&self, user_id: UserId, is_open: bool) -> ServerResult;
+    /// Opens the room of `user_id`, held by the server instance `instance_id`.
+    async fn open_room(&self, user_id: UserId, instance_id: Uuid, options: &ShareOptions) -> ServerResult<Uuid>;
+
+    async fn close_room(&self, user_id: UserId, room_id: Uuid) -> ServerResult<bool>;
+
+    async fn select_open_rooms(&self, user_id: UserId) -> ServerResult<Vec<ShareInfo>>;
+
+    async fn select_open_room(&self, user_id: UserId, room_id: Uuid) -> ServerResult<ShareInfo>;
 
     async fn is_open_room(&self, user_id: UserId) -> ServerResult<bool>;
+
+    /// Counts the rooms open on any server instance.
+    async fn count_open_rooms(&self) -> ServerResult<i64>;
+
+    /// Takes one of the open room's clone slots and returns the room id,
+    /// or `None` if the clone limit has been reached. The check and the increment are one statement,
+    /// so concurrent clones cannot exceed the limit.
+    async fn reserve_clone(&self, user_id: UserId) -> ServerResult<Option<Uuid>>;
+
+    /// Gives back a slot taken by [`RoomsTable::reserve_clone`] when no pack was sent,
+    /// unless the room has been reopened since.
+    async fn release_clone(&self, user_id: UserId, room_id: Uuid) -> ServerResult;
+
+    async fn is_clone_limit_reached(&self, user_id: UserId) -> ServerResult<bool>;
 }
 
 impl RoomsTable for PgPool {
-    async fn update_room_status(&self, user_id: UserId, is_open: bool) -> ServerResult {
-        sqlx::query(r#"
-        INSERT INTO rooms(user_id, is_open) VALUES($1, $2)
-        ON CONFLICT(user_id) DO UPDATE SET is_open=$2
+    async fn open_room(&self, user_id: UserId, instance_id: Uuid, options: &ShareOptions) -> ServerResult<Uuid> {
+        let max_clones = max_clones(options)?;
+        let row = sqlx::query(r#"
+        INSERT INTO rooms(user_id, is_open, max_clones, repository, readonly, instance_id) VALUES($1, true, $2, $3, $4, $5)
+        ON CONFLICT(user_id) DO UPDATE SET
+            is_open=true,
+            max_clones=$2,
+            repository=$3,
+            readonly=$4,
+            instance_id=$5,
+            clone_count=0,
+            room_id=gen_random_uuid(),
+            opened_at=CURRENT_TIMESTAMP
+        RETURNING room_id
         "#)
             .bind(user_id.0)
-            .bind(is_open)
+            .bind(max_clones)
+            .bind(&options.repository)
+            .bind(options.readonly)
+            .bind(instance_id)
+            .fetch_one(self)
+            .await?;
+        Ok(row.get(0))
+    }
+
+    async fn close_room(&self, user_id: UserId, room_id: Uuid) -> ServerResult<bool> {
+        let result = sqlx::query(r#"
+        UPDATE rooms SET is_open=false WHERE user_id=$1 AND room_id=$2 AND is_open=true
+        "#)
+            .bind(user_id.0)
+            .bind(room_id)
             .execute(self)
             .await?;
-        Ok(())
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn select_open_rooms(&self, user_id: UserId) -> ServerResult<Vec<ShareInfo>> {
+        let rows = sqlx::query(&format!("{SELECT_SHARE_INFO} WHERE user_id=$1 AND is_open=true"))
+            .bind(user_id.0)
+            .fetch_all(self)
+            .await?;
+        Ok(rows.iter().map(share_info).collect())
+    }
+
+    async fn select_open_room(&self, user_id: UserId, room_id: Uuid) -> ServerResult<ShareInfo> {
+        let row = sqlx::query(&format!("{SELECT_SHARE_INFO} WHERE user_id=$1 AND room_id=$2 AND is_open=true"))
+            .bind(user_id.0)
+            .bind(room_id)
+            .fetch_optional(self)
+            .await?
+            .ok_or(ServerError::ShareNotFound)?;
+        Ok(share_info(&row))
     }
 
     async fn is_open_room(&self, user_id: UserId) -> ServerResult<bool> {
@@ -34,15 +115,81 @@ impl RoomsTable for PgPool {
             Err(e) => Err(ServerError::Sqlx(e))
         }
     }
+
+    async fn count_open_rooms(&self) -> ServerResult<i64> {
+        let row = sqlx::query("SELECT count(*) FROM rooms WHERE is_open=true")
+            .fetch_one(self)
+            .await?;
+        Ok(row.get(0))
+    }
+
+    async fn reserve_clone(&self, user_id: UserId) -> ServerResult<Option<Uuid>> {
+        let row = sqlx::query(r#"
+        UPDATE rooms SET clone_count=clone_count + 1
+        WHERE user_id=$1 AND is_open=true AND (max_clones IS NULL OR clone_count < max_clones)
+        RETURNING room_id
+        "#)
+            .bind(user_id.0)
+            .fetch_optional(self)
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    async fn release_clone(&self, user_id: UserId, room_id: Uuid) -> ServerResult {
+        sqlx::query(r#"
+        UPDATE rooms SET clone_count=clone_count - 1 WHERE user_id=$1 AND room_id=$2 AND 0 < clone_count
+        "#)
+            .bind(user_id.0)
+            .bind(room_id)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn is_clone_limit_reached(&self, user_id: UserId) -> ServerResult<bool> {
+        let row = sqlx::query(r#"
+        SELECT max_clones IS NOT NULL AND max_clones <= clone_count FROM rooms WHERE user_id=$1
+        "#)
+            .bind(user_id.0)
+            .fetch_optional(self)
+            .await?;
+        Ok(row.is_some_and(|row| row.get(0)))
+    }
+}
+
+/// The clone limit as stored in the `INTEGER` column; larger limits are rejected rather than wrapped.
+pub fn max_clones(options: &ShareOptions) -> ServerResult<Option<i32>> {
+    options
+        .max_clones
+        .map(i32::try_from)
+        .transpose()
+        .map_err(|_| ServerError::InvalidMaxClones)
+}
+
+fn share_info(row: &PgRow) -> ShareInfo {
+    ShareInfo {
+        id: row.get(0),
+        repository: row.get(1),
+        readonly: row.get(2),
+        opened_at: row.get(3),
+        max_clones: row.get::<Option<i32>, _>(4).map(|max| max as u32),
+        clone_count: row.get::<i32, _>(5) as u32,
+        guest_count: row.get::<i64, _>(6) as u32,
+        remote_url: None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::db::channel::guest::new_request;
     use crate::db::rooms::RoomsTable;
+    use crate::db::test::INSTANCE1;
+    use crate::db::access_log::tests::access_log;
+    use crate::db::access_log::AccessLogTable;
     use crate::error::ServerError;
     use crate::middleware::user_id::UserId;
     use crate::test::TestResult;
+    use gph_core::types::ShareOptions;
     use sqlx::{PgPool, Row};
 
     #[sqlx::test]
@@ -51,9 +198,18 @@ mod tests {
         assert!(matches!(result, Err(ServerError::UserRoomIsNotOpen)));
     }
 
+    #[sqlx::test]
+    async fn err_if_max_clones_out_of_range(pool: PgPool) -> TestResult {
+        let options = ShareOptions { max_clones: Some(u32::MAX), ..Default::default() };
+        let result = pool.open_room(UserId::USER1, INSTANCE1, &options).await;
+        assert!(matches!(result, Err(ServerError::InvalidMaxClones)));
+        assert!(matches!(pool.is_open_room(UserId::USER1).await, Err(ServerError::UserRoomIsNotOpen)));
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn ok_open(pool: PgPool) -> TestResult {
-        pool.update_room_status(UserId::USER1, true).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
         let is_open = pool.is_open_room(UserId::USER1).await?;
         assert!(is_open);
         Ok(())
@@ -61,7 +217,8 @@ mod tests {
 
     #[sqlx::test]
     async fn ok_close_room(pool: PgPool) -> TestResult {
-        pool.update_room_status(UserId::USER1, false).await?;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert!(pool.close_room(UserId::USER1, room_id).await?);
         let is_open = pool.is_open_room(UserId::USER1).await?;
         assert!(!is_open);
         Ok(())
@@ -69,9 +226,9 @@ mod tests {
 
     #[sqlx::test]
     async fn ok_delete_request_after_close_room(pool: PgPool) -> TestResult {
-        pool.update_room_status(UserId::USER1, true).await?;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
         new_request(&pool, UserId::USER1, &[]).await?;
-        pool.update_room_status(UserId::USER1, false).await?;
+        pool.close_room(UserId::USER1, room_id).await?;
         let count: i64 = sqlx::query("SELECT count(*) FROM requests where user_id=$1")
             .bind(UserId::USER1.0)
             .fetch_one(&pool)
@@ -80,4 +237,117 @@ mod tests {
         assert_eq!(count, 0);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[sqlx::test]
+    async fn clone_limit_not_reached_if_unlimited(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert!(pool.reserve_clone(UserId::USER1).await?.is_some());
+        assert!(!pool.is_clone_limit_reached(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn clone_limit_reached(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(1), ..Default::default() }).await?;
+        assert!(!pool.is_clone_limit_reached(UserId::USER1).await?);
+        assert!(pool.reserve_clone(UserId::USER1).await?.is_some());
+        assert!(pool.is_clone_limit_reached(UserId::USER1).await?);
+        assert!(pool.reserve_clone(UserId::USER1).await?.is_none());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn concurrent_clones_within_limit(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(2), ..Default::default() }).await?;
+        let reservations = (0..10).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.reserve_clone(UserId::USER1).await.unwrap() })
+        }).collect::<Vec<_>>();
+        let mut reserved = 0;
+        for reservation in reservations {
+            reserved += reservation.await?.is_some() as usize;
+        }
+        assert_eq!(reserved, 2);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn released_clone_can_be_reserved_again(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(1), ..Default::default() }).await?;
+        let room_id = pool.reserve_clone(UserId::USER1).await?.unwrap();
+        pool.release_clone(UserId::USER1, room_id).await?;
+        assert!(pool.reserve_clone(UserId::USER1).await?.is_some());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn release_ignored_if_room_reopened(pool: PgPool) -> TestResult {
+        let options = ShareOptions { max_clones: Some(1), ..Default::default() };
+        pool.open_room(UserId::USER1, INSTANCE1, &options).await?;
+        let old_room_id = pool.reserve_clone(UserId::USER1).await?.unwrap();
+        pool.open_room(UserId::USER1, INSTANCE1, &options).await?;
+        pool.reserve_clone(UserId::USER1).await?.unwrap();
+        pool.release_clone(UserId::USER1, old_room_id).await?;
+        assert!(pool.is_clone_limit_reached(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn clone_count_reset_if_reopen(pool: PgPool) -> TestResult {
+        let options = ShareOptions { max_clones: Some(1), ..Default::default() };
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &options).await?;
+        pool.reserve_clone(UserId::USER1).await?;
+        pool.close_room(UserId::USER1, room_id).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &options).await?;
+        assert!(!pool.is_clone_limit_reached(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_close_room_if_reopened(pool: PgPool) -> TestResult {
+        let old_room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert!(!pool.close_room(UserId::USER1, old_room_id).await?);
+        assert!(pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_close_room_of_other_user(pool: PgPool) -> TestResult {
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        assert!(!pool.close_room(UserId(2), room_id).await?);
+        assert!(pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_select_open_rooms(pool: PgPool) -> TestResult {
+        let options = ShareOptions {
+            repository: Some("sample.git".to_string()),
+            readonly: true,
+            max_clones: Some(3),
+        };
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &options).await?;
+        pool.insert_access_log(&access_log(UserId::USER1)).await?;
+        pool.insert_access_log(&access_log(UserId::USER1)).await?;
+
+        let rooms = pool.select_open_rooms(UserId::USER1).await?;
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].id, room_id);
+        assert_eq!(rooms[0].repository.as_deref(), Some("sample.git"));
+        assert!(rooms[0].readonly);
+        assert_eq!(rooms[0].max_clones, Some(3));
+        assert_eq!(rooms[0].guest_count, 1);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn closed_rooms_are_not_selected(pool: PgPool) -> TestResult {
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.close_room(UserId::USER1, room_id).await?;
+        assert!(pool.select_open_rooms(UserId::USER1).await?.is_empty());
+        let result = pool.select_open_room(UserId::USER1, room_id).await;
+        assert!(matches!(result, Err(ServerError::ShareNotFound)));
+        Ok(())
+    }
+}
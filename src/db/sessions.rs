@@ -0,0 +1,332 @@
+use crate::error::{ServerError, ServerResult};
+use crate::middleware::session_token::{SessionToken, SessionTokenHash};
+use crate::middleware::user_id::UserId;
+use crate::state::{SessionExpiry, SessionKey};
+use async_trait::async_trait;
+use gph_core::types::SessionInfo;
+use sqlx::types::Uuid;
+use sqlx::{PgPool, Row};
+
+#[async_trait]
+pub trait SessionsTable {
+    /// Starts a new session for the user, leaving the sessions on other devices untouched.
+    async fn insert_session(&self, user_id: &UserId, device_name: Option<&str>, token_hash: &SessionTokenHash) -> ServerResult;
+
+    async fn select_user_id(&self, token_hash: &SessionTokenHash, expiry: &SessionExpiry) -> ServerResult<UserId>;
+
+    async fn revoke_session_token(&self, token_hash: &SessionTokenHash) -> ServerResult;
+
+    /// Replaces the token of a session. The session keeps its `created_at`,
+    /// so rotating does not extend the absolute expiry.
+    async fn rotate_session_token(&self, old_hash: &SessionTokenHash, new_hash: &SessionTokenHash) -> ServerResult;
+
+    /// Lists the user's sessions, flagging the one identified by `current`.
+    async fn select_sessions(&self, user_id: &UserId, current: &SessionTokenHash) -> ServerResult<Vec<SessionInfo>>;
+
+    async fn delete_session(&self, user_id: &UserId, session_id: Uuid) -> ServerResult<bool>;
+
+    /// Replaces the plaintext tokens of sessions created before tokens were hashed.
+    async fn hash_plaintext_session_tokens(&self, key: &SessionKey) -> ServerResult<u64>;
+}
+
+#[async_trait]
+impl SessionsTable for PgPool {
+    async fn insert_session(&self, user_id: &UserId, device_name: Option<&str>, token_hash: &SessionTokenHash) -> ServerResult {
+        sqlx::query("INSERT INTO sessions(user_id, device_name, token_hash) VALUES($1, $2, $3)")
+            .bind(user_id.0)
+            .bind(device_name)
+            .bind(&token_hash.0)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn select_user_id(&self, token_hash: &SessionTokenHash, expiry: &SessionExpiry) -> ServerResult<UserId> {
+        let row = sqlx::query(r#"
+        SELECT
+            sessions.user_id,
+            sessions.created_at + make_interval(secs => $2) < CURRENT_TIMESTAMP
+                OR sessions.last_used_at + make_interval(secs => $3) < CURRENT_TIMESTAMP,
+            users.banned_at IS NOT NULL
+        FROM sessions LEFT JOIN users ON users.user_id=sessions.user_id
+        WHERE sessions.token_hash=$1
+        "#)
+            .bind(&token_hash.0)
+            .bind(expiry.absolute.as_secs_f64())
+            .bind(expiry.idle.as_secs_f64())
+            .fetch_optional(self)
+            .await?
+            .ok_or(ServerError::InvalidSessionToken)?;
+        if row.get::<bool, _>(1) {
+            return Err(ServerError::SessionExpired);
+        }
+        if row.get::<bool, _>(2) {
+            return Err(ServerError::UserBanned);
+        }
+        sqlx::query("UPDATE sessions SET last_used_at=CURRENT_TIMESTAMP WHERE token_hash=$1")
+            .bind(&token_hash.0)
+            .execute(self)
+            .await?;
+        Ok(UserId(row.get(0)))
+    }
+
+    async fn revoke_session_token(&self, token_hash: &SessionTokenHash) -> ServerResult {
+        let result = sqlx::query("DELETE FROM sessions WHERE token_hash=$1")
+            .bind(&token_hash.0)
+            .execute(self)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(ServerError::InvalidSessionToken);
+        }
+        Ok(())
+    }
+
+    async fn rotate_session_token(&self, old_hash: &SessionTokenHash, new_hash: &SessionTokenHash) -> ServerResult {
+        let result = sqlx::query(r#"
+        UPDATE sessions SET
+            token_hash=$2,
+            last_used_at=CURRENT_TIMESTAMP
+        WHERE token_hash=$1
+        "#)
+            .bind(&old_hash.0)
+            .bind(&new_hash.0)
+            .execute(self)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(ServerError::InvalidSessionToken);
+        }
+        Ok(())
+    }
+
+    async fn select_sessions(&self, user_id: &UserId, current: &SessionTokenHash) -> ServerResult<Vec<SessionInfo>> {
+        let rows = sqlx::query(r#"
+        SELECT
+            session_id,
+            device_name,
+            to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+            to_char(last_used_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS"Z"'),
+            token_hash=$2
+        FROM sessions WHERE user_id=$1
+        ORDER BY last_used_at DESC
+        "#)
+            .bind(user_id.0)
+            .bind(&current.0)
+            .fetch_all(self)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| SessionInfo {
+                id: row.get(0),
+                device_name: row.get(1),
+                created_at: row.get(2),
+                last_used_at: row.get(3),
+                current: row.get(4),
+            })
+            .collect())
+    }
+
+    async fn delete_session(&self, user_id: &UserId, session_id: Uuid) -> ServerResult<bool> {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id=$1 AND session_id=$2")
+            .bind(user_id.0)
+            .bind(session_id)
+            .execute(self)
+            .await?;
+        Ok(0 < result.rows_affected())
+    }
+
+    async fn hash_plaintext_session_tokens(&self, key: &SessionKey) -> ServerResult<u64> {
+        let rows = sqlx::query("SELECT session_id, session_token FROM sessions WHERE token_hash IS NULL")
+            .fetch_all(self)
+            .await?;
+        for row in &rows {
+            let session_token = SessionToken(row.get::<Uuid, _>(1).to_string());
+            sqlx::query("UPDATE sessions SET token_hash=$2, session_token=NULL WHERE session_id=$1")
+                .bind(row.get::<Uuid, _>(0))
+                .bind(key.hash(&session_token).0)
+                .execute(self)
+                .await?;
+        }
+        Ok(rows.len() as u64)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::db::admin::AdminTable;
+    use crate::db::sessions::SessionsTable;
+    use crate::db::test::DBInit;
+    use crate::error::ServerError;
+    use crate::middleware::session_token::{SessionToken, SessionTokenHash};
+    use crate::middleware::user_id::UserId;
+    use crate::state::{SessionExpiry, SessionKey};
+    use crate::test::TestResult;
+    use sqlx::types::Uuid;
+    use sqlx::{PgPool, Row};
+    use std::time::Duration;
+
+    fn new_hash() -> SessionTokenHash {
+        SessionKey::test().hash(&SessionToken::generate())
+    }
+
+    async fn insert_session(pool: &PgPool) -> SessionTokenHash {
+        let token_hash = new_hash();
+        pool.insert_user(UserId::USER1).await;
+        pool.insert_session(&UserId::USER1, Some("laptop"), &token_hash).await.unwrap();
+        token_hash
+    }
+
+    #[sqlx::test]
+    async fn ok_select_user_id(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        let user = pool.select_user_id(&token_hash, &SessionExpiry::default()).await?;
+        assert_eq!(user, UserId::USER1);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_if_not_exists(pool: PgPool) {
+        let result = pool.select_user_id(&new_hash(), &SessionExpiry::default()).await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidSessionToken))
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_if_banned(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        pool.ban_user(UserId::USER1, None).await?;
+        let result = pool.select_user_id(&token_hash, &SessionExpiry::default()).await.unwrap_err();
+        assert!(matches!(result, ServerError::UserBanned));
+
+        pool.unban_user(UserId::USER1).await?;
+        assert_eq!(pool.select_user_id(&token_hash, &SessionExpiry::default()).await?, UserId::USER1);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn sessions_on_other_devices_remain_valid(pool: PgPool) -> TestResult {
+        let laptop = insert_session(&pool).await;
+        let desktop = new_hash();
+        pool.insert_session(&UserId::USER1, Some("desktop"), &desktop).await?;
+        assert_eq!(pool.select_user_id(&laptop, &SessionExpiry::default()).await?, UserId::USER1);
+        assert_eq!(pool.select_user_id(&desktop, &SessionExpiry::default()).await?, UserId::USER1);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_after_revoke(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        pool.revoke_session_token(&token_hash).await?;
+        let result = pool.select_user_id(&token_hash, &SessionExpiry::default()).await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidSessionToken));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_revoke_if_not_exists(pool: PgPool) {
+        let result = pool.revoke_session_token(&new_hash()).await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidSessionToken))
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_if_absolute_expired(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        sqlx::query("UPDATE sessions SET created_at=CURRENT_TIMESTAMP - interval '2 hours'")
+            .execute(&pool)
+            .await?;
+        let expiry = SessionExpiry {
+            absolute: Duration::from_secs(60 * 60),
+            ..SessionExpiry::default()
+        };
+        let result = pool.select_user_id(&token_hash, &expiry).await.unwrap_err();
+        assert!(matches!(result, ServerError::SessionExpired));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_if_absolute_expired_after_rotate(pool: PgPool) -> TestResult {
+        let old_hash = insert_session(&pool).await;
+        sqlx::query("UPDATE sessions SET created_at=CURRENT_TIMESTAMP - interval '2 hours'")
+            .execute(&pool)
+            .await?;
+        let new_hash = new_hash();
+        pool.rotate_session_token(&old_hash, &new_hash).await?;
+        let expiry = SessionExpiry {
+            absolute: Duration::from_secs(60 * 60),
+            ..SessionExpiry::default()
+        };
+        let result = pool.select_user_id(&new_hash, &expiry).await.unwrap_err();
+        assert!(matches!(result, ServerError::SessionExpired));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_select_user_id_if_idle_expired(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        sqlx::query("UPDATE sessions SET last_used_at=CURRENT_TIMESTAMP - interval '2 hours'")
+            .execute(&pool)
+            .await?;
+        let expiry = SessionExpiry {
+            idle: Duration::from_secs(60 * 60),
+            ..SessionExpiry::default()
+        };
+        let result = pool.select_user_id(&token_hash, &expiry).await.unwrap_err();
+        assert!(matches!(result, ServerError::SessionExpired));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_select_user_id_after_rotate(pool: PgPool) -> TestResult {
+        let old_hash = insert_session(&pool).await;
+        let new_hash = new_hash();
+        pool.rotate_session_token(&old_hash, &new_hash).await?;
+        let user = pool.select_user_id(&new_hash, &SessionExpiry::default()).await?;
+        assert_eq!(user, UserId::USER1);
+        let result = pool.select_user_id(&old_hash, &SessionExpiry::default()).await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidSessionToken));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_select_sessions(pool: PgPool) -> TestResult {
+        let laptop = insert_session(&pool).await;
+        pool.insert_session(&UserId::USER1, Some("desktop"), &new_hash()).await?;
+        let sessions = pool.select_sessions(&UserId::USER1, &laptop).await?;
+        assert_eq!(sessions.len(), 2);
+        let current = sessions.iter().find(|session| session.current).unwrap();
+        assert_eq!(current.device_name.as_deref(), Some("laptop"));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_delete_session(pool: PgPool) -> TestResult {
+        let token_hash = insert_session(&pool).await;
+        let sessions = pool.select_sessions(&UserId::USER1, &token_hash).await?;
+        assert!(pool.delete_session(&UserId::USER1, sessions[0].id).await?);
+        assert!(!pool.delete_session(&UserId::USER1, sessions[0].id).await?);
+        let result = pool.select_user_id(&token_hash, &SessionExpiry::default()).await.unwrap_err();
+        assert!(matches!(result, ServerError::InvalidSessionToken));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn plaintext_tokens_are_hashed(pool: PgPool) -> TestResult {
+        let legacy_token = Uuid::new_v4();
+        pool.insert_user(UserId::USER1).await;
+        sqlx::query("INSERT INTO sessions(user_id, session_token) VALUES($1, $2)")
+            .bind(UserId::USER1.0)
+            .bind(legacy_token)
+            .execute(&pool)
+            .await?;
+        assert_eq!(pool.hash_plaintext_session_tokens(&SessionKey::test()).await?, 1);
+        assert_eq!(pool.hash_plaintext_session_tokens(&SessionKey::test()).await?, 0);
+
+        let token_hash = SessionKey::test().hash(&SessionToken(legacy_token.to_string()));
+        assert_eq!(pool.select_user_id(&token_hash, &SessionExpiry::default()).await?, UserId::USER1);
+        let plaintext = sqlx::query("SELECT count(*) FROM sessions WHERE session_token IS NOT NULL")
+            .fetch_one(&pool)
+            .await?
+            .get::<i64, _>(0);
+        assert_eq!(plaintext, 0);
+        Ok(())
+    }
+}
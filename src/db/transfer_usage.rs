@@ -0,0 +1,168 @@
+use crate::error::ServerResult;
+use crate::middleware::user_id::UserId;
+use sqlx::{PgPool, Row};
+
+const TODAY: &str = "(CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::date";
+
+/// Bytes relayed in the current UTC day and month.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Usage {
+    pub daily_bytes: i64,
+    pub monthly_bytes: i64,
+}
+
+pub trait TransferUsageTable {
+    /// Adds `bytes` received from and sent to guests of `user_id`'s share to today's usage.
+    async fn add_transfer(&self, user_id: UserId, bytes: i64) -> ServerResult;
+
+    /// Adds `bytes` to today's usage only if neither quota is used up and the usage stays within both,
+    /// returning whether it was added.
+    ///
+    /// Today's row is checked and updated in one statement, so concurrent charges cannot pass the quota together.
+    async fn charge_transfer(
+        &self,
+        user_id: UserId,
+        bytes: i64,
+        daily_quota: Option<u64>,
+        monthly_quota: Option<u64>,
+    ) -> ServerResult<bool>;
+
+    async fn select_transfer_usage(&self, user_id: UserId) -> ServerResult<Usage>;
+}
+
+impl TransferUsageTable for PgPool {
+    async fn add_transfer(&self, user_id: UserId, bytes: i64) -> ServerResult {
+        sqlx::query(&format!(r#"
+        INSERT INTO transfer_usage(user_id, day, bytes) VALUES($1, {TODAY}, $2)
+        ON CONFLICT(user_id, day) DO UPDATE SET bytes=transfer_usage.bytes + $2
+        "#))
+            .bind(user_id.0)
+            .bind(bytes)
+            .execute(self)
+            .await?;
+        Ok(())
+    }
+
+    async fn charge_transfer(
+        &self,
+        user_id: UserId,
+        bytes: i64,
+        daily_quota: Option<u64>,
+        monthly_quota: Option<u64>,
+    ) -> ServerResult<bool> {
+        // Earlier days of the month no longer change, so only today's row needs to be locked.
+        let earlier: i64 = sqlx::query_scalar(&format!(r#"
+        SELECT COALESCE(SUM(bytes), 0)::BIGINT
+        FROM transfer_usage
+        WHERE user_id=$1 AND date_trunc('month', {TODAY}) <= day AND day < {TODAY}
+        "#))
+            .bind(user_id.0)
+            .fetch_one(self)
+            .await?;
+        let quota = |quota: Option<u64>| quota.map(|quota| i64::try_from(quota).unwrap_or(i64::MAX));
+        let charged = sqlx::query(&format!(r#"
+        INSERT INTO transfer_usage(user_id, day, bytes)
+        SELECT $1, {TODAY}, $2
+        WHERE ($3::BIGINT IS NULL OR (0 < $3 AND $2 <= $3))
+            AND ($4::BIGINT IS NULL OR ($5 < $4 AND $5 + $2 <= $4))
+        ON CONFLICT(user_id, day) DO UPDATE SET bytes=transfer_usage.bytes + $2
+        WHERE ($3::BIGINT IS NULL OR (transfer_usage.bytes < $3 AND transfer_usage.bytes + $2 <= $3))
+            AND ($4::BIGINT IS NULL OR ($5 + transfer_usage.bytes < $4 AND $5 + transfer_usage.bytes + $2 <= $4))
+        "#))
+            .bind(user_id.0)
+            .bind(bytes)
+            .bind(quota(daily_quota))
+            .bind(quota(monthly_quota))
+            .bind(earlier)
+            .execute(self)
+            .await?;
+        Ok(charged.rows_affected() == 1)
+    }
+
+    async fn select_transfer_usage(&self, user_id: UserId) -> ServerResult<Usage> {
+        let row = sqlx::query(&format!(r#"
+        SELECT
+            COALESCE(SUM(bytes) FILTER (WHERE day={TODAY}), 0)::BIGINT,
+            COALESCE(SUM(bytes), 0)::BIGINT
+        FROM transfer_usage
+        WHERE user_id=$1 AND date_trunc('month', {TODAY}) <= day
+        "#))
+            .bind(user_id.0)
+            .fetch_one(self)
+            .await?;
+        Ok(Usage {
+            daily_bytes: row.get(0),
+            monthly_bytes: row.get(1),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::transfer_usage::{TransferUsageTable, Usage};
+    use crate::middleware::user_id::UserId;
+    use crate::test::TestResult;
+    use sqlx::PgPool;
+
+    #[sqlx::test]
+    async fn zero_if_no_transfer(pool: PgPool) -> TestResult {
+        assert_eq!(pool.select_transfer_usage(UserId::USER1).await?, Usage::default());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn sum_transfers(pool: PgPool) -> TestResult {
+        pool.add_transfer(UserId::USER1, 100).await?;
+        pool.add_transfer(UserId::USER1, 20).await?;
+        pool.add_transfer(UserId(2), 5).await?;
+        sqlx::query("INSERT INTO transfer_usage(user_id, day, bytes) VALUES($1, (CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::date - 40, 1000)")
+            .bind(UserId::USER1.0)
+            .execute(&pool)
+            .await?;
+
+        let usage = pool.select_transfer_usage(UserId::USER1).await?;
+        assert_eq!(usage.daily_bytes, 120);
+        assert_eq!(usage.monthly_bytes, 120);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn charge_within_quota(pool: PgPool) -> TestResult {
+        assert!(pool.charge_transfer(UserId::USER1, 60, Some(100), None).await?);
+        assert!(pool.charge_transfer(UserId::USER1, 40, Some(100), None).await?);
+        assert!(!pool.charge_transfer(UserId::USER1, 0, Some(100), None).await?);
+        assert!(pool.charge_transfer(UserId(2), 0, Some(100), None).await?);
+        assert_eq!(pool.select_transfer_usage(UserId::USER1).await?.daily_bytes, 100);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn charge_counts_earlier_days_against_monthly_quota(pool: PgPool) -> TestResult {
+        sqlx::query("INSERT INTO transfer_usage(user_id, day, bytes) VALUES($1, date_trunc('month', CURRENT_TIMESTAMP AT TIME ZONE 'UTC')::date, 90)")
+            .bind(UserId::USER1.0)
+            .execute(&pool)
+            .await?;
+        // On the first day of the month the row above is today's, which the quota counts just the same.
+        assert!(!pool.charge_transfer(UserId::USER1, 20, None, Some(100)).await?);
+        assert!(pool.charge_transfer(UserId::USER1, 10, None, Some(100)).await?);
+        assert!(!pool.charge_transfer(UserId::USER1, 0, None, Some(100)).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn concurrent_charges_within_quota(pool: PgPool) -> TestResult {
+        let charges = (0..10).map(|_| {
+            let pool = pool.clone();
+            tokio::spawn(async move { pool.charge_transfer(UserId::USER1, 30, Some(100), None).await })
+        });
+        let mut charged = 0;
+        for charge in charges.collect::<Vec<_>>() {
+            if charge.await?? {
+                charged += 1;
+            }
+        }
+        assert_eq!(charged, 3);
+        assert_eq!(pool.select_transfer_usage(UserId::USER1).await?.daily_bytes, 90);
+        Ok(())
+    }
+}
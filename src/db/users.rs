@@ -1,85 +1,83 @@
-use crate::error::{ServerError, ServerResult};
-use crate::middleware::session_token::SessionToken;
+use crate::error::ServerResult;
 use crate::middleware::user_id::UserId;
 use async_trait::async_trait;
 use sqlx::{PgPool, Row};
 
 #[async_trait]
 pub trait UsersTable {
-    async fn insert_into_users(&self, user_id: &UserId) -> ServerResult<SessionToken>;
+    /// Returns the user who logged in with the identity, creating the user on first login.
+    async fn select_or_insert_user(&self, provider: &str, subject: &str) -> ServerResult<UserId>;
 
-    async fn select_from_users(&self, session_token: &SessionToken) -> ServerResult<UserId>;
+    async fn is_banned(&self, user_id: UserId) -> ServerResult<bool>;
 }
 
 #[async_trait]
 impl UsersTable for PgPool {
-    async fn insert_into_users(&self, user_id: &UserId) -> ServerResult<SessionToken> {
-        let row = sqlx::query(r#"
-        INSERT INTO users(user_id) VALUES($1)
-        ON CONFLICT(user_id) DO UPDATE SET session_token=gen_random_uuid(), created_at=CURRENT_TIMESTAMP
-        RETURNING session_token
-        "#)
-            .bind(user_id.0)
-            .fetch_one(self)
+    async fn select_or_insert_user(&self, provider: &str, subject: &str) -> ServerResult<UserId> {
+        let mut tx = self.begin().await?;
+        let identity = sqlx::query("SELECT user_id FROM identities WHERE provider=$1 AND subject=$2")
+            .bind(provider)
+            .bind(subject)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if let Some(row) = identity {
+            return Ok(UserId(row.get(0)));
+        }
+        let user_id = sqlx::query("INSERT INTO users DEFAULT VALUES RETURNING user_id")
+            .fetch_one(&mut *tx)
+            .await?
+            .get(0);
+        sqlx::query("INSERT INTO identities(provider, subject, user_id) VALUES($1, $2, $3)")
+            .bind(provider)
+            .bind(subject)
+            .bind(user_id)
+            .execute(&mut *tx)
             .await?;
-        Ok(SessionToken(row.get(0)))
+        tx.commit().await?;
+        Ok(UserId(user_id))
     }
 
-    async fn select_from_users(&self, session_token: &SessionToken) -> ServerResult<UserId> {
-        let result = sqlx::query(r#"
-        SELECT user_id FROM users WHERE session_token=$1
-        "#)
-            .bind(session_token.0)
+    async fn is_banned(&self, user_id: UserId) -> ServerResult<bool> {
+        let banned = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE user_id=$1 AND banned_at IS NOT NULL)")
+            .bind(user_id.0)
             .fetch_one(self)
-            .await;
-        match result {
-            Ok(row) => {
-                Ok(UserId(row.get(0)))
-            }
-            Err(sqlx::Error::RowNotFound) => {
-                Err(ServerError::InvalidSessionToken)
-            }
-            Err(e) => {
-                Err(ServerError::Sqlx(e))
-            }
-        }
+            .await?;
+        Ok(banned)
     }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use crate::db::admin::AdminTable;
     use crate::db::users::UsersTable;
-    use crate::error::ServerError;
-    use crate::middleware::session_token::SessionToken;
-    use crate::middleware::user_id::UserId;
     use crate::test::TestResult;
     use sqlx::PgPool;
 
     #[sqlx::test]
-    async fn ok_insert_user(pool: PgPool) {
-        pool.insert_into_users(&UserId::USER1).await.unwrap();
-    }
-
-    #[sqlx::test]
-    async fn ok_select_user(pool: PgPool) -> TestResult {
-        let session_token = pool.insert_into_users(&UserId::USER1).await?;
-        let user = pool.select_from_users(&session_token).await?;
-        assert_eq!(user, UserId::USER1);
+    async fn same_user_if_same_identity(pool: PgPool) -> TestResult {
+        let user1 = pool.select_or_insert_user("github", "1").await?;
+        let user2 = pool.select_or_insert_user("github", "1").await?;
+        assert_eq!(user1, user2);
         Ok(())
     }
 
     #[sqlx::test]
-    async fn err_select_user_if_not_exists(pool: PgPool) {
-        let result = pool.select_from_users(&SessionToken::max()).await.unwrap_err();
-        assert!(matches!(result, ServerError::InvalidSessionToken))
+    async fn other_user_if_other_provider(pool: PgPool) -> TestResult {
+        let github_user = pool.select_or_insert_user("github", "1").await?;
+        let gitlab_user = pool.select_or_insert_user("gitlab", "1").await?;
+        assert_ne!(github_user, gitlab_user);
+        Ok(())
     }
 
     #[sqlx::test]
-    async fn session_token_update_if_insert_again(pool: PgPool) -> TestResult {
-        let session_token1 = pool.insert_into_users(&UserId::USER1).await?;
-        let session_token2 = pool.insert_into_users(&UserId::USER1).await?;
-        assert_ne!(session_token1, session_token2);
+    async fn banned_until_unbanned(pool: PgPool) -> TestResult {
+        let user_id = pool.select_or_insert_user("github", "1").await?;
+        assert!(!pool.is_banned(user_id).await?);
+        pool.ban_user(user_id, None).await?;
+        assert!(pool.is_banned(user_id).await?);
+        pool.unban_user(user_id).await?;
+        assert!(!pool.is_banned(user_id).await?);
         Ok(())
     }
-}
\ No newline at end of file
+}
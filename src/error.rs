@@ -1,5 +1,5 @@
 use axum::body::Body;
-use axum::http::StatusCode;
+use axum::http::{header, StatusCode};
 use axum::response::{IntoResponse, Response};
 use std::error::Error;
 use thiserror::Error;
@@ -11,27 +11,96 @@ pub enum ServerError {
     #[error("Missing auth code in query")]
     MissingAuthCode,
 
-    #[error("Failed to connect github api")]
-    FailedConnectGithubApi,
+    #[error("Invalid or expired oauth state; please try logging in again")]
+    InvalidOAuthState,
+
+    #[error("The redirect uri must be a loopback http address")]
+    InvalidRedirectUri,
+
+    #[error("The login nonce must be 32 to 128 letters, digits, `-` or `_`")]
+    InvalidLoginNonce,
+
+    #[error("The device code has expired; please run `gph auth --device` again")]
+    DeviceCodeExpired,
+
+    #[error("The login was denied")]
+    DeviceAccessDenied,
+
+    #[error("Unknown identity provider")]
+    UnknownIdentityProvider,
+
+    #[error("The identity provider does not support device-code login")]
+    DeviceFlowUnsupported,
+
+    #[error("Failed to connect to the identity provider")]
+    FailedConnectIdentityProvider,
 
     #[error("User room is not open")]
     UserRoomIsNotOpen,
 
+    #[error("Share not found")]
+    ShareNotFound,
+
+    #[error("This share has reached its clone limit and is no longer available")]
+    ShareUsedUp,
+
     #[error("Invalid session token")]
     InvalidSessionToken,
 
+    #[error("Session not found")]
+    SessionNotFound,
+
+    #[error("Session expired")]
+    SessionExpired,
+
+    #[error("This account has been banned")]
+    UserBanned,
+
+    #[error("Invalid admin token")]
+    InvalidAdminToken,
+
+    #[error("The admin API is disabled")]
+    AdminApiDisabled,
+
+    #[error("Invalid metrics token")]
+    InvalidMetricsToken,
+
+    #[error("Metrics are disabled")]
+    MetricsDisabled,
+
+    #[error("User not found")]
+    UserNotFound,
+
     #[error("Required session token")]
     RequiredSessionToken,
 
     #[error("Failed parse request body")]
     FailedParseRequestBody,
 
+    #[error("max_clones must be at most 2147483647")]
+    InvalidMaxClones,
+
     #[error("Failed recv git response")]
     FailedRecvGitResponse,
 
     #[error("Failed parse git response")]
     FailedParseGitResponse,
 
+    #[error("The owner did not respond in time")]
+    OwnerResponseTimeout,
+
+    #[error("The server is shutting down")]
+    ServerShuttingDown,
+
+    #[error("The request body is too large")]
+    PayloadTooLarge,
+
+    #[error("The share's transfer quota is used up")]
+    TransferQuotaExceeded,
+
+    #[error("Too many requests; retry after {retry_after} seconds")]
+    RateLimited { retry_after: u64 },
+
     #[cfg_attr(test, error("sqlx error: {0}"))]
     #[cfg_attr(not(test), error("internal server error"))]
     Sqlx(#[from] sqlx::Error),
@@ -40,10 +109,18 @@ pub enum ServerError {
 impl ServerError {
     pub fn as_status(&self) -> StatusCode {
         match self {
-            Self::MissingAuthCode | Self::FailedRecvGitResponse | Self::FailedParseRequestBody => StatusCode::BAD_REQUEST,
-            Self::InvalidSessionToken | Self::RequiredSessionToken => StatusCode::UNAUTHORIZED,
-            Self::UserRoomIsNotOpen => StatusCode::NOT_FOUND,
-            Self::FailedParseGitResponse | Self::FailedConnectGithubApi | Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::MissingAuthCode | Self::InvalidOAuthState | Self::InvalidRedirectUri | Self::InvalidLoginNonce | Self::DeviceCodeExpired
+            | Self::UnknownIdentityProvider | Self::DeviceFlowUnsupported | Self::FailedRecvGitResponse | Self::FailedParseRequestBody
+            | Self::InvalidMaxClones => StatusCode::BAD_REQUEST,
+            Self::InvalidSessionToken | Self::RequiredSessionToken | Self::SessionExpired | Self::InvalidAdminToken | Self::InvalidMetricsToken => StatusCode::UNAUTHORIZED,
+            Self::UserRoomIsNotOpen | Self::ShareNotFound | Self::SessionNotFound | Self::AdminApiDisabled | Self::MetricsDisabled | Self::UserNotFound => StatusCode::NOT_FOUND,
+            Self::ShareUsedUp => StatusCode::GONE,
+            Self::DeviceAccessDenied | Self::TransferQuotaExceeded | Self::UserBanned => StatusCode::FORBIDDEN,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::OwnerResponseTimeout => StatusCode::GATEWAY_TIMEOUT,
+            Self::ServerShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::FailedParseGitResponse | Self::FailedConnectIdentityProvider | Self::Sqlx(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -55,8 +132,13 @@ impl IntoResponse for ServerError {
             tracing::error!("{}", self.source().unwrap_or(&self));
         }
 
-        Response::builder()
+        let mut response = Response::builder()
             .status(status_code)
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8");
+        if let Self::RateLimited { retry_after } = self {
+            response = response.header(header::RETRY_AFTER, retry_after);
+        }
+        response
             .body(Body::from(self.to_string()))
             .unwrap()
     }
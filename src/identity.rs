@@ -0,0 +1,276 @@
+mod gitea;
+mod github;
+mod gitlab;
+mod oidc;
+
+use crate::db::oauth_states::OAuthState;
+use crate::error::{ServerError, ServerResult};
+use async_trait::async_trait;
+use axum::http::header;
+use gph_core::types::{DeviceAuthorization, DeviceTokenResponse};
+use oauth2::basic::BasicClient;
+use oauth2::{AuthUrl, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge, RedirectUrl, Scope, TokenUrl};
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub use gitea::GiteaProvider;
+pub use github::{GithubCredentials, GithubProvider};
+pub use gitlab::GitlabProvider;
+pub use oidc::OidcProvider;
+
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// An OAuth2 server users can log in with.
+///
+/// Users are identified by the pair of [`IdentityProvider::name`] and the subject returned by
+/// [`IdentityProvider::fetch_subject`], so the name must not change once users have logged in.
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn endpoints(&self) -> &ProviderEndpoints;
+
+    /// Returns the stable id of the user who owns `access_token`.
+    async fn fetch_subject(&self, access_token: &str) -> ServerResult<String>;
+}
+
+/// The OAuth2 client registration of a provider.
+#[derive(Clone, Debug)]
+pub struct ProviderEndpoints {
+    pub client_id: ClientId,
+    pub client_secret: ClientSecret,
+    pub auth_url: AuthUrl,
+    pub token_url: TokenUrl,
+    /// `None` if the provider does not support the device authorization flow.
+    pub device_authorization_url: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+/// The providers enabled on this server. The first one is used when a client does not name one.
+#[derive(Clone)]
+pub struct IdentityProviders(Arc<Vec<Arc<dyn IdentityProvider>>>);
+
+impl IdentityProviders {
+    pub fn new(providers: Vec<Arc<dyn IdentityProvider>>) -> Self {
+        Self(Arc::new(providers))
+    }
+
+    /// Enables each provider whose client id is set in the environment.
+    pub async fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut providers: Vec<Arc<dyn IdentityProvider>> = Vec::new();
+        if let Some(credentials) = GithubCredentials::load()? {
+            providers.push(Arc::new(GithubProvider::new(credentials)));
+        }
+        if let Some(gitlab) = GitlabProvider::load()? {
+            providers.push(Arc::new(gitlab));
+        }
+        if let Some(gitea) = GiteaProvider::load()? {
+            providers.push(Arc::new(gitea));
+        }
+        if let Some(oidc) = OidcProvider::load().await? {
+            providers.push(Arc::new(oidc));
+        }
+        if providers.is_empty() {
+            return Err("No identity provider is configured".into());
+        }
+        Ok(Self::new(providers))
+    }
+
+    pub fn get(&self, name: Option<&str>) -> ServerResult<Arc<dyn IdentityProvider>> {
+        let provider = match name {
+            Some(name) => self.0.iter().find(|provider| provider.name() == name),
+            None => self.0.first(),
+        };
+        provider.cloned().ok_or(ServerError::UnknownIdentityProvider)
+    }
+}
+
+/// Builds the url the user is sent to, returning it with the state that identifies the login attempt.
+pub fn authorize_url(
+    provider: &dyn IdentityProvider,
+    redirect_url: Option<RedirectUrl>,
+    pkce_challenge: PkceCodeChallenge,
+) -> (oauth2::url::Url, CsrfToken) {
+    let endpoints = provider.endpoints();
+    let mut client = BasicClient::new(
+        endpoints.client_id.clone(),
+        Some(endpoints.client_secret.clone()),
+        endpoints.auth_url.clone(),
+        Some(endpoints.token_url.clone()),
+    );
+    if let Some(redirect_url) = redirect_url {
+        client = client.set_redirect_uri(redirect_url);
+    }
+    client
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(endpoints.scopes.iter().cloned().map(Scope::new))
+        .set_pkce_challenge(pkce_challenge)
+        .url()
+}
+
+/// Exchanges the authorization code the user was redirected back with for an access token.
+pub async fn exchange_code(
+    provider: &dyn IdentityProvider,
+    code: &str,
+    oauth_state: &OAuthState,
+) -> ServerResult<String> {
+    let endpoints = provider.endpoints();
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("client_id", endpoints.client_id.as_str()),
+        ("client_secret", endpoints.client_secret.secret()),
+        ("code", code),
+        ("code_verifier", &oauth_state.pkce_verifier),
+    ];
+    if let Some(redirect_uri) = &oauth_state.redirect_uri {
+        form.push(("redirect_uri", redirect_uri));
+    }
+    request_token(endpoints, &form)
+        .await?
+        .into_result()?
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)
+}
+
+pub async fn request_device_code(provider: &dyn IdentityProvider) -> ServerResult<DeviceAuthorization> {
+    let endpoints = provider.endpoints();
+    let Some(device_authorization_url) = &endpoints.device_authorization_url else {
+        return Err(ServerError::DeviceFlowUnsupported);
+    };
+    let scope = endpoints.scopes.join(" ");
+    reqwest::Client::new()
+        .post(device_authorization_url)
+        .header(header::ACCEPT, "application/json")
+        .form(&[("client_id", endpoints.client_id.as_str()), ("scope", &scope)])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)?
+        .json::<DeviceAuthorization>()
+        .await
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)
+}
+
+/// Checks once whether the user has entered the device code,
+/// returning the access token, or the response telling the client to keep polling.
+pub async fn poll_device_token(
+    provider: &dyn IdentityProvider,
+    device_code: &str,
+) -> ServerResult<Result<String, DeviceTokenResponse>> {
+    let endpoints = provider.endpoints();
+    if endpoints.device_authorization_url.is_none() {
+        return Err(ServerError::DeviceFlowUnsupported);
+    }
+    let form = [
+        ("grant_type", DEVICE_GRANT_TYPE),
+        ("client_id", endpoints.client_id.as_str()),
+        ("device_code", device_code),
+    ];
+    request_token(endpoints, &form).await?.into_result()
+}
+
+/// Token errors are answered with `400 Bad Request` by most providers but `200 OK` by GitHub,
+/// so the body is parsed regardless of the status.
+async fn request_token(endpoints: &ProviderEndpoints, form: &[(&str, &str)]) -> ServerResult<TokenResponse> {
+    reqwest::Client::new()
+        .post(endpoints.token_url.as_str())
+        .header(header::ACCEPT, "application/json")
+        .form(form)
+        .send()
+        .await
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)?
+        .json::<TokenResponse>()
+        .await
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+}
+
+impl TokenResponse {
+    fn into_result(self) -> ServerResult<Result<String, DeviceTokenResponse>> {
+        if let Some(access_token) = self.access_token {
+            return Ok(Ok(access_token));
+        }
+        match self.error.as_deref() {
+            Some("authorization_pending") => Ok(Err(DeviceTokenResponse::Pending)),
+            Some("slow_down") => Ok(Err(DeviceTokenResponse::SlowDown)),
+            Some("expired_token") => Err(ServerError::DeviceCodeExpired),
+            Some("access_denied") => Err(ServerError::DeviceAccessDenied),
+            _ => Err(ServerError::FailedConnectIdentityProvider),
+        }
+    }
+}
+
+/// Fetches the user info at `url` and reads the subject from `key`, which may be a number or a string.
+async fn fetch_user_field(url: &str, access_token: &str, key: &str) -> ServerResult<String> {
+    let user = reqwest::Client::new()
+        .get(url)
+        .header(header::USER_AGENT, "git_phantom")
+        .header(header::ACCEPT, "application/json")
+        .bearer_auth(access_token)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)?
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|_| ServerError::FailedConnectIdentityProvider)?;
+    user_field(&user, key).ok_or(ServerError::FailedConnectIdentityProvider)
+}
+
+fn user_field(user: &serde_json::Value, key: &str) -> Option<String> {
+    match user.get(key)? {
+        serde_json::Value::Number(number) => Some(number.to_string()),
+        serde_json::Value::String(string) if !string.is_empty() => Some(string.clone()),
+        _ => None,
+    }
+}
+
+fn env_var(key: &str) -> Option<String> {
+    std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ServerError;
+    use crate::identity::{user_field, TokenResponse};
+    use gph_core::types::DeviceTokenResponse;
+
+    fn token_response(json: &str) -> TokenResponse {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn keep_polling_while_pending() {
+        let result = token_response(r#"{"error":"authorization_pending"}"#).into_result().unwrap();
+        assert_eq!(result, Err(DeviceTokenResponse::Pending));
+        let result = token_response(r#"{"error":"slow_down","interval":10}"#).into_result().unwrap();
+        assert_eq!(result, Err(DeviceTokenResponse::SlowDown));
+    }
+
+    #[test]
+    fn ok_access_token() {
+        let result = token_response(r#"{"access_token":"token","token_type":"bearer","scope":""}"#).into_result().unwrap();
+        assert_eq!(result, Ok("token".to_string()));
+    }
+
+    #[test]
+    fn err_if_expired_or_denied() {
+        let result = token_response(r#"{"error":"expired_token"}"#).into_result().unwrap_err();
+        assert!(matches!(result, ServerError::DeviceCodeExpired));
+        let result = token_response(r#"{"error":"access_denied"}"#).into_result().unwrap_err();
+        assert!(matches!(result, ServerError::DeviceAccessDenied));
+    }
+
+    #[test]
+    fn subject_from_number_or_string() {
+        let user = serde_json::json!({ "id": 42, "sub": "abc", "empty": "" });
+        assert_eq!(user_field(&user, "id").as_deref(), Some("42"));
+        assert_eq!(user_field(&user, "sub").as_deref(), Some("abc"));
+        assert_eq!(user_field(&user, "empty"), None);
+        assert_eq!(user_field(&user, "missing"), None);
+    }
+}
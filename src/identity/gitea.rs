@@ -0,0 +1,54 @@
+use crate::error::ServerResult;
+use crate::identity::{env_var, fetch_user_field, IdentityProvider, ProviderEndpoints};
+use async_trait::async_trait;
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
+
+/// A Gitea or Forgejo instance, which share the same OAuth2 and user APIs.
+pub struct GiteaProvider {
+    base_url: String,
+    endpoints: ProviderEndpoints,
+}
+
+impl GiteaProvider {
+    pub fn new(base_url: &str, client_id: ClientId, client_secret: ClientSecret) -> Result<Self, oauth2::url::ParseError> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Ok(Self {
+            endpoints: ProviderEndpoints {
+                client_id,
+                client_secret,
+                auth_url: AuthUrl::new(format!("{base_url}/login/oauth/authorize"))?,
+                token_url: TokenUrl::new(format!("{base_url}/login/oauth/access_token"))?,
+                device_authorization_url: None,
+                scopes: vec!["read:user".to_string()],
+            },
+            base_url,
+        })
+    }
+
+    /// Reads `GITEA_URL`, `GITEA_CLIENT_ID` and `GITEA_CLIENT_SECRET`.
+    pub fn load() -> Result<Option<Self>, oauth2::url::ParseError> {
+        let (Some(base_url), Some(client_id), Some(client_secret)) = (
+            env_var("GITEA_URL"),
+            env_var("GITEA_CLIENT_ID"),
+            env_var("GITEA_CLIENT_SECRET"),
+        ) else {
+            return Ok(None);
+        };
+        Self::new(&base_url, ClientId::new(client_id), ClientSecret::new(client_secret)).map(Some)
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for GiteaProvider {
+    fn name(&self) -> &str {
+        "gitea"
+    }
+
+    fn endpoints(&self) -> &ProviderEndpoints {
+        &self.endpoints
+    }
+
+    async fn fetch_subject(&self, access_token: &str) -> ServerResult<String> {
+        fetch_user_field(&format!("{}/api/v1/user", self.base_url), access_token, "id").await
+    }
+}
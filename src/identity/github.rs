@@ -0,0 +1,164 @@
+use crate::error::ServerResult;
+use crate::identity::{env_var, fetch_user_field, IdentityProvider, ProviderEndpoints};
+use async_trait::async_trait;
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
+
+const GITHUB_URL: &str = "https://github.com";
+const GITHUB_API_URL: &str = "https://api.github.com";
+
+#[derive(Clone)]
+pub struct GithubCredentials {
+    /// The provider name stored with each identity; see [`GithubCredentials::load`].
+    pub name: String,
+    pub client_id: ClientId,
+    pub client_secret: ClientSecret,
+    pub auth_url: AuthUrl,
+    pub token_url: TokenUrl,
+    pub device_authorization_url: String,
+    /// The REST API base, such as `https://api.github.com` or `https://github.example.com/api/v3`.
+    pub api_url: String,
+}
+
+impl GithubCredentials {
+    /// Builds the endpoints of github.com, or of the GitHub Enterprise Server at `github_url`.
+    ///
+    /// The API is expected at `{github_url}/api/v3` unless `api_url` is given. The provider is named `github`
+    /// for github.com and after the host otherwise, so accounts on different servers never share an identity.
+    pub fn new(
+        client_id: ClientId,
+        client_secret: ClientSecret,
+        github_url: Option<&str>,
+        api_url: Option<&str>,
+    ) -> Result<GithubCredentials, oauth2::url::ParseError> {
+        let github_url = github_url.unwrap_or(GITHUB_URL).trim_end_matches('/');
+        let api_url = match api_url {
+            Some(api_url) => api_url.to_string(),
+            None if github_url == GITHUB_URL => GITHUB_API_URL.to_string(),
+            None => format!("{github_url}/api/v3"),
+        };
+        let name = if github_url == GITHUB_URL {
+            "github".to_string()
+        } else {
+            let url = oauth2::url::Url::parse(github_url)?;
+            match (url.host_str(), url.port()) {
+                (Some(host), Some(port)) => format!("{host}:{port}"),
+                (Some(host), None) => host.to_string(),
+                (None, _) => return Err(oauth2::url::ParseError::EmptyHost),
+            }
+        };
+        Ok(GithubCredentials {
+            name,
+            client_id,
+            client_secret,
+            auth_url: AuthUrl::new(format!("{github_url}/login/oauth/authorize"))?,
+            token_url: TokenUrl::new(format!("{github_url}/login/oauth/access_token"))?,
+            device_authorization_url: format!("{github_url}/login/device/code"),
+            api_url: api_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Reads `CLIENT_ID`, `CLIENT_SECRET`, and optionally `GITHUB_URL`, `GITHUB_API_URL` and
+    /// `GITHUB_PROVIDER_NAME`.
+    ///
+    /// The provider name keys the stored identities, so it must not change once users have signed in;
+    /// set `GITHUB_PROVIDER_NAME` to keep an existing name when moving `GITHUB_URL`.
+    pub fn load() -> Result<Option<GithubCredentials>, oauth2::url::ParseError> {
+        let (Some(client_id), Some(client_secret)) = (env_var("CLIENT_ID"), env_var("CLIENT_SECRET")) else {
+            return Ok(None);
+        };
+        let mut credentials = Self::new(
+            ClientId::new(client_id),
+            ClientSecret::new(client_secret),
+            env_var("GITHUB_URL").as_deref(),
+            env_var("GITHUB_API_URL").as_deref(),
+        )?;
+        if let Some(name) = env_var("GITHUB_PROVIDER_NAME") {
+            credentials.name = name;
+        }
+        Ok(Some(credentials))
+    }
+}
+
+pub struct GithubProvider {
+    name: String,
+    api_url: String,
+    endpoints: ProviderEndpoints,
+}
+
+impl GithubProvider {
+    pub fn new(credentials: GithubCredentials) -> Self {
+        Self {
+            name: credentials.name,
+            api_url: credentials.api_url,
+            endpoints: ProviderEndpoints {
+                client_id: credentials.client_id,
+                client_secret: credentials.client_secret,
+                auth_url: credentials.auth_url,
+                token_url: credentials.token_url,
+                device_authorization_url: Some(credentials.device_authorization_url),
+                scopes: Vec::new(),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for GithubProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn endpoints(&self) -> &ProviderEndpoints {
+        &self.endpoints
+    }
+
+    async fn fetch_subject(&self, access_token: &str) -> ServerResult<String> {
+        fetch_user_field(&format!("{}/user", self.api_url), access_token, "id").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::GithubCredentials;
+    use oauth2::{ClientId, ClientSecret};
+
+    fn credentials(github_url: Option<&str>, api_url: Option<&str>) -> GithubCredentials {
+        GithubCredentials::new(
+            ClientId::new("client".to_string()),
+            ClientSecret::new("secret".to_string()),
+            github_url,
+            api_url,
+        ).unwrap()
+    }
+
+    #[test]
+    fn github_com_by_default() {
+        let credentials = credentials(None, None);
+        assert_eq!(credentials.auth_url.as_str(), "https://github.com/login/oauth/authorize");
+        assert_eq!(credentials.token_url.as_str(), "https://github.com/login/oauth/access_token");
+        assert_eq!(credentials.api_url, "https://api.github.com");
+        assert_eq!(credentials.name, "github");
+    }
+
+    #[test]
+    fn enterprise_server_api_under_base_url() {
+        let credentials = credentials(Some("https://github.example.com/"), None);
+        assert_eq!(credentials.auth_url.as_str(), "https://github.example.com/login/oauth/authorize");
+        assert_eq!(credentials.device_authorization_url, "https://github.example.com/login/device/code");
+        assert_eq!(credentials.api_url, "https://github.example.com/api/v3");
+        assert_eq!(credentials.name, "github.example.com");
+    }
+
+    #[test]
+    fn explicit_api_url() {
+        let credentials = credentials(Some("http://localhost:9000"), Some("http://localhost:9001/"));
+        assert_eq!(credentials.token_url.as_str(), "http://localhost:9000/login/oauth/access_token");
+        assert_eq!(credentials.api_url, "http://localhost:9001");
+        assert_eq!(credentials.name, "localhost:9000");
+    }
+
+    #[test]
+    fn github_com_named_github_with_trailing_slash() {
+        assert_eq!(credentials(Some("https://github.com/"), None).name, "github");
+    }
+}
@@ -0,0 +1,51 @@
+use crate::error::ServerResult;
+use crate::identity::{env_var, fetch_user_field, IdentityProvider, ProviderEndpoints};
+use async_trait::async_trait;
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
+
+/// GitLab.com or a self-hosted GitLab instance.
+pub struct GitlabProvider {
+    base_url: String,
+    endpoints: ProviderEndpoints,
+}
+
+impl GitlabProvider {
+    pub fn new(base_url: &str, client_id: ClientId, client_secret: ClientSecret) -> Result<Self, oauth2::url::ParseError> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Ok(Self {
+            endpoints: ProviderEndpoints {
+                client_id,
+                client_secret,
+                auth_url: AuthUrl::new(format!("{base_url}/oauth/authorize"))?,
+                token_url: TokenUrl::new(format!("{base_url}/oauth/token"))?,
+                device_authorization_url: Some(format!("{base_url}/oauth/authorize_device")),
+                scopes: vec!["read_user".to_string()],
+            },
+            base_url,
+        })
+    }
+
+    /// Reads `GITLAB_URL` (`https://gitlab.com` by default), `GITLAB_CLIENT_ID` and `GITLAB_CLIENT_SECRET`.
+    pub fn load() -> Result<Option<Self>, oauth2::url::ParseError> {
+        let (Some(client_id), Some(client_secret)) = (env_var("GITLAB_CLIENT_ID"), env_var("GITLAB_CLIENT_SECRET")) else {
+            return Ok(None);
+        };
+        let base_url = env_var("GITLAB_URL").unwrap_or_else(|| "https://gitlab.com".to_string());
+        Self::new(&base_url, ClientId::new(client_id), ClientSecret::new(client_secret)).map(Some)
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for GitlabProvider {
+    fn name(&self) -> &str {
+        "gitlab"
+    }
+
+    fn endpoints(&self) -> &ProviderEndpoints {
+        &self.endpoints
+    }
+
+    async fn fetch_subject(&self, access_token: &str) -> ServerResult<String> {
+        fetch_user_field(&format!("{}/api/v4/user", self.base_url), access_token, "id").await
+    }
+}
@@ -0,0 +1,105 @@
+use crate::error::ServerResult;
+use crate::identity::{env_var, fetch_user_field, IdentityProvider, ProviderEndpoints};
+use async_trait::async_trait;
+use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
+use serde::Deserialize;
+
+/// Any OpenID Connect provider, configured through its discovery document.
+pub struct OidcProvider {
+    name: String,
+    userinfo_endpoint: String,
+    endpoints: ProviderEndpoints,
+}
+
+/// The fields of `/.well-known/openid-configuration` needed for login.
+#[derive(Deserialize, Debug)]
+struct DiscoveryDocument {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+    device_authorization_endpoint: Option<String>,
+}
+
+impl OidcProvider {
+    /// Reads `OIDC_ISSUER`, `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET` and `OIDC_NAME` (`oidc` by default),
+    /// then fetches the issuer's discovery document.
+    pub async fn load() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let (Some(issuer), Some(client_id), Some(client_secret)) = (
+            env_var("OIDC_ISSUER"),
+            env_var("OIDC_CLIENT_ID"),
+            env_var("OIDC_CLIENT_SECRET"),
+        ) else {
+            return Ok(None);
+        };
+        let discovery = reqwest::get(format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/')))
+            .await?
+            .error_for_status()?
+            .json::<DiscoveryDocument>()
+            .await?;
+        let name = env_var("OIDC_NAME").unwrap_or_else(|| "oidc".to_string());
+        Ok(Some(Self::new(name, discovery, ClientId::new(client_id), ClientSecret::new(client_secret))?))
+    }
+
+    fn new(
+        name: String,
+        discovery: DiscoveryDocument,
+        client_id: ClientId,
+        client_secret: ClientSecret,
+    ) -> Result<Self, oauth2::url::ParseError> {
+        Ok(Self {
+            name,
+            userinfo_endpoint: discovery.userinfo_endpoint,
+            endpoints: ProviderEndpoints {
+                client_id,
+                client_secret,
+                auth_url: AuthUrl::new(discovery.authorization_endpoint)?,
+                token_url: TokenUrl::new(discovery.token_endpoint)?,
+                device_authorization_url: discovery.device_authorization_endpoint,
+                scopes: vec!["openid".to_string()],
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for OidcProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn endpoints(&self) -> &ProviderEndpoints {
+        &self.endpoints
+    }
+
+    async fn fetch_subject(&self, access_token: &str) -> ServerResult<String> {
+        fetch_user_field(&self.userinfo_endpoint, access_token, "sub").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::identity::oidc::{DiscoveryDocument, OidcProvider};
+    use crate::identity::IdentityProvider;
+    use oauth2::{ClientId, ClientSecret};
+
+    #[test]
+    fn endpoints_from_discovery_document() {
+        let discovery = serde_json::from_str::<DiscoveryDocument>(r#"{
+            "issuer": "https://id.example.com",
+            "authorization_endpoint": "https://id.example.com/authorize",
+            "token_endpoint": "https://id.example.com/token",
+            "userinfo_endpoint": "https://id.example.com/userinfo",
+            "jwks_uri": "https://id.example.com/jwks"
+        }"#).unwrap();
+        let provider = OidcProvider::new(
+            "company".to_string(),
+            discovery,
+            ClientId::new("client".to_string()),
+            ClientSecret::new("secret".to_string()),
+        ).unwrap();
+        assert_eq!(provider.name(), "company");
+        assert_eq!(provider.endpoints().token_url.as_str(), "https://id.example.com/token");
+        assert_eq!(provider.endpoints().device_authorization_url, None);
+        assert_eq!(provider.userinfo_endpoint, "https://id.example.com/userinfo");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::db::instances::InstancesTable;
+use crate::error::ServerResult;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How often an instance records that it is alive.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// An instance without a heartbeat for this long is considered crashed and its rooms are closed.
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// This server process: its id in `server_instances` and its shutdown state.
+///
+/// Shutdown happens in steps. Once [`ServerInstance::shutdown`] is called no new git requests are relayed,
+/// while the owners stay connected until the requests already relayed are [`ServerInstance::drained`].
+/// Then each owner's websocket is closed, which [`ServerInstance::owners_disconnected`] waits for.
+#[derive(Debug, Clone)]
+pub struct ServerInstance(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    id: Uuid,
+    shutting_down: watch::Sender<bool>,
+    in_flight: watch::Sender<usize>,
+    owners: watch::Sender<usize>,
+}
+
+impl ServerInstance {
+    pub fn new() -> Self {
+        Self(Arc::new(Inner {
+            id: Uuid::new_v4(),
+            shutting_down: watch::Sender::new(false),
+            in_flight: watch::Sender::new(0),
+            owners: watch::Sender::new(0),
+        }))
+    }
+
+    #[inline]
+    pub fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    pub fn shutdown(&self) {
+        self.0.shutting_down.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.shutting_down.borrow()
+    }
+
+    /// Resolves once [`ServerInstance::shutdown`] has been called.
+    pub async fn shutting_down(&self) {
+        let _ = self.0.shutting_down.subscribe().wait_for(|shutting_down| *shutting_down).await;
+    }
+
+    /// Resolves once the server is shutting down and no relayed git request is waiting for a response.
+    pub async fn drained(&self) {
+        self.shutting_down().await;
+        let _ = self.0.in_flight.subscribe().wait_for(|in_flight| *in_flight == 0).await;
+    }
+
+    pub fn connected_owners(&self) -> usize {
+        *self.0.owners.borrow()
+    }
+
+    /// Resolves once no owner is connected.
+    pub async fn owners_disconnected(&self) {
+        let _ = self.0.owners.subscribe().wait_for(|owners| *owners == 0).await;
+    }
+
+    /// Counts a relayed git request as in flight until the returned guard is dropped.
+    pub fn track_request(&self) -> Tracked {
+        Tracked::new(self.clone(), |inner| &inner.in_flight)
+    }
+
+    /// Counts an owner as connected until the returned guard is dropped.
+    pub fn track_owner(&self) -> Tracked {
+        Tracked::new(self.clone(), |inner| &inner.owners)
+    }
+
+    /// Registers this instance, closes rooms left open by crashed instances,
+    /// and keeps doing both periodically in the background.
+    pub async fn start_heartbeat(&self, pool: PgPool) -> ServerResult {
+        pool.heartbeat_instance(self.id()).await?;
+        let closed = pool.close_stale_rooms(HEARTBEAT_TIMEOUT).await?;
+        if 0 < closed {
+            tracing::info!("Closed {closed} rooms left open by stopped instances");
+        }
+        let instance = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(HEARTBEAT_INTERVAL);
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    _ = instance.shutting_down() => return,
+                }
+                if let Err(e) = pool.heartbeat_instance(instance.id()).await {
+                    tracing::error!("Failed to record heartbeat: {e}");
+                }
+                if let Err(e) = pool.close_stale_rooms(HEARTBEAT_TIMEOUT).await {
+                    tracing::error!("Failed to close stale rooms: {e}");
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+impl Default for ServerInstance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decrements one of the instance's counters when dropped.
+pub struct Tracked {
+    instance: ServerInstance,
+    counter: fn(&Inner) -> &watch::Sender<usize>,
+}
+
+impl Tracked {
+    fn new(instance: ServerInstance, counter: fn(&Inner) -> &watch::Sender<usize>) -> Self {
+        counter(&instance.0).send_modify(|count| *count += 1);
+        Self { instance, counter }
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        (self.counter)(&self.instance.0).send_modify(|count| *count -= 1);
+    }
+}
+
+/// Resolves on `SIGTERM` or Ctrl-C.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instance::ServerInstance;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn drained_waits_for_in_flight_requests() {
+        let instance = ServerInstance::new();
+        let request = instance.track_request();
+        instance.shutdown();
+        assert!(tokio::time::timeout(Duration::from_millis(50), instance.drained()).await.is_err());
+
+        drop(request);
+        tokio::time::timeout(Duration::from_millis(50), instance.drained()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_owners_disconnected() {
+        let instance = ServerInstance::new();
+        let owner = instance.track_owner();
+        assert!(tokio::time::timeout(Duration::from_millis(50), instance.owners_disconnected()).await.is_err());
+
+        drop(owner);
+        tokio::time::timeout(Duration::from_millis(50), instance.owners_disconnected()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn not_drained_before_shutdown() {
+        let instance = ServerInstance::new();
+        assert!(!instance.is_shutting_down());
+        assert!(tokio::time::timeout(Duration::from_millis(50), instance.drained()).await.is_err());
+    }
+}
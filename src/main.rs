@@ -3,93 +3,185 @@ mod db;
 mod middleware;
 mod error;
 mod state;
-
-use crate::state::{AppState, GithubCredentials};
-use axum::routing::put;
+mod identity;
+mod config;
+mod tls;
+mod instance;
+mod metrics;
+mod logging;
+mod command;
+
+use crate::command::{Cli, Command};
+use crate::config::ServerConfig;
+use crate::db::instances::InstancesTable;
+use crate::db::sessions::SessionsTable;
+use crate::identity::IdentityProviders;
+use crate::instance::{shutdown_signal, ServerInstance};
+use crate::middleware::rate_limit::{rate_limit, RateLimits};
+use crate::state::{AppState, SessionExpiry, SessionKey};
+use axum::routing::{delete, post, put};
 use axum::{routing::get, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use clap::Parser;
+use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
-    let db_url = std::env::var("DATABASE_URL")?;
-    let pool = PgPool::connect(&db_url).await?;
+    let cli = Cli::parse();
+    let config = ServerConfig::load()?;
+    logging::init(&config.log);
+    let pool = PgPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .connect(config.database.url.as_deref().unwrap_or_default())
+        .await?;
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(config, pool).await,
+        Command::Migrate => {
+            sqlx::migrate!().run(&pool).await?;
+            tracing::info!("The database is up to date");
+            Ok(())
+        }
+        Command::Admin(command) => command.execute(&pool, &config).await,
+    }
+}
+
+async fn serve(config: ServerConfig, pool: PgPool) -> Result<(), Box<dyn Error>> {
     sqlx::migrate!()
         .run(&pool)
         .await
         .expect("Failed to run migrate");
-    let app = app(AppState {
-        pool,
-        github_credentials: GithubCredentials::load(),
+    let session_key = SessionKey::new(config.session_token_key()?);
+    pool.hash_plaintext_session_tokens(&session_key).await?;
+    let instance = ServerInstance::new();
+    instance.start_heartbeat(pool.clone()).await?;
+    tokio::spawn({
+        let instance = instance.clone();
+        async move {
+            shutdown_signal().await;
+            instance.shutdown();
+        }
     });
-    #[cfg(debug_assertions)]
-    http::start_server(app).await?;
-    #[cfg(not(debug_assertions))]
-    https::start_server(app).await?;
-    Ok(())
-}
 
-#[cfg(debug_assertions)]
-mod http {
-    use axum::Router;
-    use tokio::net::TcpListener;
+    let config = Arc::new(config);
+    let rate_limits = RateLimits::new(config.rate_limit);
+    rate_limits.spawn_sweeper();
+    let app = app(AppState {
+        pool: pool.clone(),
+        identity_providers: IdentityProviders::load().await?,
+        session_expiry: SessionExpiry::load(),
+        session_key,
+        config: config.clone(),
+        instance: instance.clone(),
+        rate_limits,
+    });
+    start_server(&config, app, &instance).await?;
 
-    pub async fn start_server(app: Router) -> Result<(), Box<dyn std::error::Error>> {
-        let listener = TcpListener::bind("0.0.0.0:8080").await?;
-        axum::serve(listener, app).await?;
-        Ok(())
+    let shutdown_timeout = config.server.shutdown_timeout();
+    if tokio::time::timeout(shutdown_timeout, instance.owners_disconnected()).await.is_err() {
+        tracing::warn!("Timed out waiting for owners to disconnect");
     }
+    pool.remove_instance(instance.id()).await?;
+    Ok(())
 }
 
-#[cfg(not(debug_assertions))]
-mod https {
-    use axum::Router;
-    use axum_server::tls_rustls::RustlsConfig;
-    use std::net::SocketAddr;
-    use std::path::PathBuf;
-
-    pub async fn start_server(app: Router) -> Result<(), Box<dyn std::error::Error>> {
-        let config = RustlsConfig::from_pem_file(
-            PathBuf::from(std::env::var("CERT_PEM").expect("Failed to read CERT_PEM")),
-            PathBuf::from(std::env::var("KEY_PEM").expect("Failed to read KEY_PEM")),
-        )
-            .await?;
-
-        let addr = SocketAddr::from(([0, 0, 0, 0], 443));
-        axum_server::bind_rustls(addr, config)
-            .serve(app.into_make_service())
-            .await?;
-        Ok(())
+/// Serves until the instance shuts down, then stops accepting connections
+/// and waits up to the shutdown timeout for requests in progress.
+async fn start_server(config: &ServerConfig, app: Router, instance: &ServerInstance) -> Result<(), Box<dyn Error>> {
+    let addr = config.socket_addr();
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    let shutdown_timeout = config.server.shutdown_timeout();
+    match (&config.tls.cert_path, &config.tls.key_path) {
+        (Some(cert_path), Some(key_path)) if config.tls.enabled => {
+            let tls_config = RustlsConfig::from_pem_file(cert_path, key_path).await?;
+            tls::spawn_reloader(tls_config.clone(), cert_path.clone(), key_path.clone(), config.tls.reload_interval());
+            let handle = Handle::new();
+            tokio::spawn({
+                let handle = handle.clone();
+                let instance = instance.clone();
+                async move {
+                    instance.shutting_down().await;
+                    handle.graceful_shutdown(Some(shutdown_timeout));
+                }
+            });
+            axum_server::bind_rustls(addr, tls_config).handle(handle).serve(service).await?;
+        }
+        _ => {
+            let listener = TcpListener::bind(addr).await?;
+            let server = axum::serve(listener, service).with_graceful_shutdown({
+                let instance = instance.clone();
+                async move { instance.shutting_down().await }
+            });
+            tokio::select! {
+                result = server => result?,
+                _ = async {
+                    instance.shutting_down().await;
+                    tokio::time::sleep(shutdown_timeout).await;
+                } => tracing::warn!("Timed out waiting for requests to finish"),
+            }
+        }
     }
+    Ok(())
 }
 
-
 fn app(app_state: AppState) -> Router {
     Router::new()
         .nest("/oauth2", oauth2_router())
+        .nest("/admin", admin_router())
         .route("/user_id", get(route::user_id))
+        .route("/log", get(route::log))
+        .route("/usage", get(route::usage))
+        .route("/session", delete(route::delete_session))
+        .route("/session/refresh", post(route::refresh_session))
+        .route("/sessions", get(route::list_sessions))
+        .route("/sessions/:session_id", delete(route::revoke_session))
         .route("/share", get(route::share))
+        .route("/shares", get(route::list_shares))
+        .route("/shares/:id", get(route::get_share).delete(route::close_share))
         .route("/git/:user_id/*path", get(route::git).post(route::git))
+        .route("/metrics", get(route::metrics))
+        .layer(axum::middleware::from_fn_with_state(app_state.clone(), rate_limit))
+        // Probes must keep working while clients are throttled.
+        .route("/healthz", get(route::healthz))
+        .route("/readyz", get(route::readyz))
         .with_state(app_state)
 }
 
+fn admin_router() -> Router<AppState> {
+    Router::new()
+        .route("/rooms", get(route::admin::list_rooms))
+        .route("/rooms/:room_id", delete(route::admin::close_room))
+        .route("/users/:user_id/ban", put(route::admin::ban_user).delete(route::admin::unban_user))
+        .route("/requests", delete(route::admin::purge_requests))
+}
+
 fn oauth2_router() -> Router<AppState> {
     Router::new()
         .route("/auth", get(route::oauth2::auth))
         .route("/register", put(route::oauth2::register))
+        .route("/device/code", post(route::oauth2::device_code))
+        .route("/device/token", post(route::oauth2::device_token))
 }
 
 
 #[cfg(test)]
 pub(crate) mod test {
     use crate::app;
-    use crate::state::{AppState, GithubCredentials};
+    use crate::identity::{GithubCredentials, GithubProvider, GitlabProvider, IdentityProviders};
+    use crate::state::{AppState, SessionExpiry, SessionKey};
     use axum::body::Body;
     use axum::extract::Request;
     use axum::Router;
+    use oauth2::{ClientId, ClientSecret};
     use sqlx::PgPool;
     use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
     use tokio::net::TcpListener;
 
     pub type TestResult<T = ()> = Result<T, Box<dyn std::error::Error>>;
@@ -97,22 +189,51 @@ pub(crate) mod test {
     pub static PORT: AtomicUsize = AtomicUsize::new(5100);
 
     pub async fn test_app(pool: PgPool) -> Router {
-        app(AppState {
+        app(test_state(pool))
+    }
+
+    pub fn test_state(pool: PgPool) -> AppState {
+        AppState {
             pool,
-            github_credentials: GithubCredentials::load(),
-        })
+            identity_providers: test_identity_providers(),
+            session_expiry: SessionExpiry::default(),
+            session_key: SessionKey::test(),
+            config: Default::default(),
+            instance: Default::default(),
+            rate_limits: Default::default(),
+        }
+    }
+
+    fn test_identity_providers() -> IdentityProviders {
+        let github = GithubProvider::new(GithubCredentials::new(
+            ClientId::new("github_client".to_string()),
+            ClientSecret::new("github_secret".to_string()),
+            None,
+            None,
+        ).unwrap());
+        let gitlab = GitlabProvider::new(
+            "https://gitlab.example.com",
+            ClientId::new("gitlab_client".to_string()),
+            ClientSecret::new("gitlab_secret".to_string()),
+        ).unwrap();
+        IdentityProviders::new(vec![Arc::new(github), Arc::new(gitlab)])
     }
 
+    pub const NONCE: &str = "0123456789abcdef0123456789abcdef";
+
     pub fn auth_request() -> Request {
-        Request::get("/oauth2/auth").body(Body::empty()).unwrap()
+        Request::get(format!("/oauth2/auth?nonce={NONCE}")).body(Body::empty()).unwrap()
     }
 
     pub async fn start_server(pool: PgPool) -> usize {
+        start_server_with(test_state(pool)).await
+    }
+
+    pub async fn start_server_with(state: AppState) -> usize {
         let port = PORT.fetch_add(1, Ordering::Relaxed);
+        let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await.unwrap();
         tokio::spawn(async move {
-            let listener = TcpListener::bind(format!("0.0.0.0:{port}")).await.unwrap();
-            let app = test_app(pool).await;
-            axum::serve(listener, app).await.unwrap();
+            axum::serve(listener, app(state)).await.unwrap();
         });
         port
     }
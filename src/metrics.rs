@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The process-wide metrics served at `/metrics`.
+pub static METRICS: Metrics = Metrics::new();
+
+/// Upper bounds of the relay latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [0.01, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10., 30., 60.];
+
+/// Counters and histograms updated by the routes; gauges are read when rendering.
+#[derive(Debug)]
+pub struct Metrics {
+    relayed_requests: Mutex<BTreeMap<(&'static str, u16), u64>>,
+    relay_latency: Mutex<BTreeMap<&'static str, Histogram>>,
+    request_bytes: AtomicU64,
+    response_bytes: AtomicU64,
+    owner_connections: AtomicU64,
+    listener_reconnects: AtomicU64,
+    rate_limited: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Values read from the database and the server instance when `/metrics` is scraped.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Gauges {
+    pub open_rooms: i64,
+    pub connected_owners: usize,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            relayed_requests: Mutex::new(BTreeMap::new()),
+            relay_latency: Mutex::new(BTreeMap::new()),
+            request_bytes: AtomicU64::new(0),
+            response_bytes: AtomicU64::new(0),
+            owner_connections: AtomicU64::new(0),
+            listener_reconnects: AtomicU64::new(0),
+            rate_limited: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Records a git request relayed to an owner. `service` is `git-upload-pack`, `git-receive-pack` or `other`.
+    pub fn record_relay(&self, service: &'static str, status: u16, latency: Duration, bytes_in: u64, bytes_out: u64) {
+        *self.relayed_requests.lock().unwrap().entry((service, status)).or_default() += 1;
+        self.relay_latency.lock().unwrap().entry(service).or_default().observe(latency);
+        self.request_bytes.fetch_add(bytes_in, Ordering::Relaxed);
+        self.response_bytes.fetch_add(bytes_out, Ordering::Relaxed);
+    }
+
+    pub fn record_owner_connection(&self) {
+        self.owner_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a Postgres listener lost its connection and reconnected.
+    pub fn record_listener_reconnect(&self) {
+        self.listener_reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a request rejected by the rate limit of `scope`: `ip`, `oauth`, `user` or `room`.
+    pub fn record_rate_limited(&self, scope: &'static str) {
+        *self.rate_limited.lock().unwrap().entry(scope).or_default() += 1;
+    }
+
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self, gauges: Gauges) -> String {
+        let mut text = String::new();
+        metric(&mut text, "gph_open_rooms", "gauge", "Rooms currently open on any instance.");
+        let _ = writeln!(text, "gph_open_rooms {}", gauges.open_rooms);
+        metric(&mut text, "gph_connected_owners", "gauge", "Owner websockets connected to this instance.");
+        let _ = writeln!(text, "gph_connected_owners {}", gauges.connected_owners);
+        metric(&mut text, "gph_owner_connections_total", "counter", "Owner websockets opened on this instance.");
+        let _ = writeln!(text, "gph_owner_connections_total {}", self.owner_connections.load(Ordering::Relaxed));
+
+        metric(&mut text, "gph_relayed_requests_total", "counter", "Git requests relayed to owners, by service and status.");
+        for ((service, status), count) in self.relayed_requests.lock().unwrap().iter() {
+            let _ = writeln!(text, "gph_relayed_requests_total{{service=\"{service}\",status=\"{status}\"}} {count}");
+        }
+        metric(&mut text, "gph_relay_request_bytes_total", "counter", "Bytes received from guests.");
+        let _ = writeln!(text, "gph_relay_request_bytes_total {}", self.request_bytes.load(Ordering::Relaxed));
+        metric(&mut text, "gph_relay_response_bytes_total", "counter", "Bytes sent to guests.");
+        let _ = writeln!(text, "gph_relay_response_bytes_total {}", self.response_bytes.load(Ordering::Relaxed));
+
+        metric(&mut text, "gph_relay_duration_seconds", "histogram", "Time from receiving a git request to responding, by service.");
+        for (service, histogram) in self.relay_latency.lock().unwrap().iter() {
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                let _ = writeln!(text, "gph_relay_duration_seconds_bucket{{service=\"{service}\",le=\"{le}\"}} {count}");
+            }
+            let _ = writeln!(text, "gph_relay_duration_seconds_bucket{{service=\"{service}\",le=\"+Inf\"}} {}", histogram.count);
+            let _ = writeln!(text, "gph_relay_duration_seconds_sum{{service=\"{service}\"}} {}", histogram.sum);
+            let _ = writeln!(text, "gph_relay_duration_seconds_count{{service=\"{service}\"}} {}", histogram.count);
+        }
+
+        metric(&mut text, "gph_pg_listener_reconnects_total", "counter", "Postgres notification listeners that lost their connection.");
+        let _ = writeln!(text, "gph_pg_listener_reconnects_total {}", self.listener_reconnects.load(Ordering::Relaxed));
+
+        metric(&mut text, "gph_rate_limited_total", "counter", "Requests rejected with 429, by rate limit.");
+        for (scope, count) in self.rate_limited.lock().unwrap().iter() {
+            let _ = writeln!(text, "gph_rate_limited_total{{scope=\"{scope}\"}} {count}");
+        }
+        text
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        for (bucket, le) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+fn metric(text: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{Gauges, Metrics};
+    use std::time::Duration;
+
+    #[test]
+    fn render_relayed_requests() {
+        let metrics = Metrics::new();
+        metrics.record_relay("git-upload-pack", 200, Duration::from_millis(30), 10, 100);
+        metrics.record_relay("git-upload-pack", 200, Duration::from_secs(2), 20, 200);
+        metrics.record_relay("other", 404, Duration::from_millis(5), 0, 0);
+
+        let text = metrics.render(Gauges { open_rooms: 2, connected_owners: 1 });
+        assert!(text.contains("gph_open_rooms 2\n"));
+        assert!(text.contains("gph_connected_owners 1\n"));
+        assert!(text.contains("gph_relayed_requests_total{service=\"git-upload-pack\",status=\"200\"} 2\n"));
+        assert!(text.contains("gph_relayed_requests_total{service=\"other\",status=\"404\"} 1\n"));
+        assert!(text.contains("gph_relay_request_bytes_total 30\n"));
+        assert!(text.contains("gph_relay_response_bytes_total 300\n"));
+        assert!(text.contains("gph_relay_duration_seconds_bucket{service=\"git-upload-pack\",le=\"0.05\"} 1\n"));
+        assert!(text.contains("gph_relay_duration_seconds_bucket{service=\"git-upload-pack\",le=\"2.5\"} 2\n"));
+        assert!(text.contains("gph_relay_duration_seconds_bucket{service=\"git-upload-pack\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("gph_relay_duration_seconds_count{service=\"other\"} 1\n"));
+    }
+
+    #[test]
+    fn render_listener_reconnects() {
+        let metrics = Metrics::new();
+        metrics.record_listener_reconnect();
+        metrics.record_rate_limited("oauth");
+        let text = metrics.render(Gauges::default());
+        assert!(text.contains("gph_rate_limited_total{scope=\"oauth\"} 1\n"));
+        assert!(text.contains("# TYPE gph_pg_listener_reconnects_total counter\n"));
+        assert!(text.contains("gph_pg_listener_reconnects_total 1\n"));
+    }
+}
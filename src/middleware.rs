@@ -1,2 +1,4 @@
 pub mod user_id;
-pub mod session_token;
\ No newline at end of file
+pub mod session_token;
+pub mod rate_limit;
+pub mod admin_token;
@@ -0,0 +1,59 @@
+use crate::error::ServerError;
+use crate::state::AppState;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::RequestPartsExt;
+use axum_extra::headers::authorization::Bearer;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
+use sha2::{Digest, Sha256};
+
+/// Proof that the request carries the configured admin token.
+///
+/// Every `/admin` route takes this extractor, so the API answers `404` while no token is configured.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminToken;
+
+#[async_trait::async_trait]
+impl FromRequestParts<AppState> for AdminToken {
+    type Rejection = ServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(expected) = state.config.admin.token.as_deref() else {
+            return Err(ServerError::AdminApiDisabled);
+        };
+        if bearer_matches(parts, expected).await {
+            Ok(AdminToken)
+        } else {
+            Err(ServerError::InvalidAdminToken)
+        }
+    }
+}
+
+/// Proof that the request carries the configured metrics token; `/metrics` answers `404` without one.
+#[derive(Debug, Clone, Copy)]
+pub struct MetricsToken;
+
+#[async_trait::async_trait]
+impl FromRequestParts<AppState> for MetricsToken {
+    type Rejection = ServerError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(expected) = state.config.admin.metrics_token.as_deref() else {
+            return Err(ServerError::MetricsDisabled);
+        };
+        if bearer_matches(parts, expected).await {
+            Ok(MetricsToken)
+        } else {
+            Err(ServerError::InvalidMetricsToken)
+        }
+    }
+}
+
+async fn bearer_matches(parts: &mut Parts, expected: &str) -> bool {
+    let Ok(TypedHeader(Authorization(bearer))) = parts.extract::<TypedHeader<Authorization<Bearer>>>().await else {
+        return false;
+    };
+    // Comparing digests keeps the time taken independent of how much of the token matches.
+    Sha256::digest(bearer.token()) == Sha256::digest(expected)
+}
@@ -0,0 +1,416 @@
+use crate::config::{RateLimit, RateLimitConfig};
+use crate::error::ServerError;
+use crate::metrics::METRICS;
+use crate::middleware::user_id::UserId;
+use crate::state::AppState;
+use axum::extract::{ConnectInfo, FromRequestParts, Request, State};
+use axum::http::{Extensions, HeaderMap};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Each limiter tracks at most this many keys, evicting the least recently used bucket beyond it.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// How often buckets that have refilled completely are dropped.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The token buckets of every rate limit, shared by all requests.
+#[derive(Debug, Clone)]
+pub struct RateLimits(Arc<Inner>);
+
+#[derive(Debug)]
+struct Inner {
+    config: RateLimitConfig,
+    ip: Limiter<IpAddr>,
+    oauth: Limiter<IpAddr>,
+    user: Limiter<UserId>,
+    room: Limiter<i64>,
+}
+
+impl RateLimits {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self(Arc::new(Inner {
+            ip: Limiter::new(config.ip),
+            oauth: Limiter::new(config.oauth),
+            user: Limiter::new(config.user),
+            room: Limiter::new(config.room),
+            config,
+        }))
+    }
+}
+
+impl RateLimits {
+    /// Periodically drops refilled buckets, so idle clients do not keep their keys tracked.
+    pub fn spawn_sweeper(&self) {
+        let limits = self.0.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let now = Instant::now();
+                limits.ip.sweep(now);
+                limits.oauth.sweep(now);
+                limits.user.sweep(now);
+                limits.room.sweep(now);
+            }
+        });
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self::new(RateLimitConfig::default())
+    }
+}
+
+/// Rejects requests over the per-IP, per-user or per-room limit with `429 Too Many Requests`.
+///
+/// `/oauth2` routes additionally share a stricter per-IP limit because each login calls the identity provider,
+/// and `/git/:user_id` requests are limited per room, whoever the guest is.
+pub async fn rate_limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let limits = &state.rate_limits.0;
+    if !limits.config.enabled {
+        return next.run(request).await;
+    }
+    let (mut parts, body) = request.into_parts();
+
+    let ip = client_ip(&parts.headers, &parts.extensions, limits.config.trust_forwarded_for);
+    let path = parts.uri.path();
+    let mut result = Ok(());
+    if let Some(ip) = ip {
+        result = result.and_then(|_| limits.ip.check("ip", ip));
+        if path.starts_with("/oauth2/") {
+            result = result.and_then(|_| limits.oauth.check("oauth", ip));
+        }
+    }
+    if let Some(room) = room_user_id(path) {
+        result = result.and_then(|_| limits.room.check("room", room));
+    }
+    if result.is_ok() && parts.headers.contains_key(axum::http::header::AUTHORIZATION) {
+        // The handler reuses the user id from the extensions instead of looking it up again.
+        if let Ok(user_id) = UserId::from_request_parts(&mut parts, &state).await {
+            parts.extensions.insert(user_id);
+            result = limits.user.check("user", user_id);
+        }
+    }
+
+    match result {
+        Ok(()) => next.run(Request::from_parts(parts, body)).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+/// The address the request came from, or the last `X-Forwarded-For` address if the proxy is trusted.
+///
+/// Proxies append the address they received the request from, so every earlier entry may have been sent by the client.
+pub fn client_ip(headers: &HeaderMap, extensions: &Extensions, trust_forwarded_for: bool) -> Option<IpAddr> {
+    let forwarded_for = trust_forwarded_for
+        .then(|| headers.get_all("X-Forwarded-For").iter().next_back())
+        .flatten()
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|addr| addr.trim().parse().ok());
+    forwarded_for.or_else(|| extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+    )
+}
+
+fn room_user_id(path: &str) -> Option<i64> {
+    path.strip_prefix("/git/")?.split('/').next()?.parse().ok()
+}
+
+#[derive(Debug)]
+struct Limiter<K> {
+    limit: RateLimit,
+    buckets: Mutex<Buckets<K>>,
+}
+
+/// The buckets by key, and the keys by when their bucket was last used.
+#[derive(Debug)]
+struct Buckets<K> {
+    by_key: HashMap<K, Bucket>,
+    by_use: BTreeMap<u64, K>,
+    next_use: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    used: u64,
+}
+
+impl<K: Eq + Hash + Copy> Limiter<K> {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                by_use: BTreeMap::new(),
+                next_use: 0,
+            }),
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        self.limit.per_minute as f64 / 60.
+    }
+
+    fn burst(&self) -> f64 {
+        self.limit.burst.max(1) as f64
+    }
+
+    fn check(&self, scope: &'static str, key: K) -> Result<(), ServerError> {
+        self.take(key, Instant::now()).map_err(|retry_after| {
+            METRICS.record_rate_limited(scope);
+            ServerError::RateLimited {
+                retry_after: retry_after.as_secs_f64().ceil().max(1.) as u64,
+            }
+        })
+    }
+
+    /// Takes a token from the bucket of `key`, or returns how long until one is available.
+    fn take(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let rate = self.rate();
+        let burst = self.burst();
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { by_key, by_use, next_use } = &mut *buckets;
+        let used = *next_use;
+        *next_use += 1;
+        if let Some(bucket) = by_key.get(&key) {
+            by_use.remove(&bucket.used);
+        } else if MAX_TRACKED_KEYS <= by_key.len() {
+            if let Some((_, evicted)) = by_use.pop_first() {
+                by_key.remove(&evicted);
+            }
+        }
+        by_use.insert(used, key);
+        let bucket = by_key.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+            used,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * rate).min(burst);
+        bucket.updated_at = now;
+        bucket.used = used;
+        if 1. <= bucket.tokens {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else if rate <= 0. {
+            Err(Duration::from_secs(60))
+        } else {
+            Err(Duration::from_secs_f64((1. - bucket.tokens) / rate))
+        }
+    }
+
+    /// Drops the buckets that have refilled completely, starting from the least recently used,
+    /// and stops at the first one that may still be short of tokens.
+    fn sweep(&self, now: Instant) {
+        let rate = self.rate();
+        if rate <= 0. {
+            return;
+        }
+        let refill = Duration::from_secs_f64(self.burst() / rate);
+        let mut buckets = self.buckets.lock().unwrap();
+        let Buckets { by_key, by_use, .. } = &mut *buckets;
+        while let Some(entry) = by_use.first_entry() {
+            let key = *entry.get();
+            if now.saturating_duration_since(by_key[&key].updated_at) < refill {
+                break;
+            }
+            entry.remove();
+            by_key.remove(&key);
+        }
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.buckets.lock().unwrap().by_key.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app;
+    use crate::config::{RateLimit, RateLimitConfig};
+    use crate::db::test::{DBInit, SESSION1};
+    use crate::middleware::rate_limit::{client_ip, room_user_id, Limiter, RateLimits, MAX_TRACKED_KEYS};
+    use crate::state::AppState;
+    use crate::test::{test_state, TestResult};
+    use axum::body::Body;
+    use axum::extract::{ConnectInfo, Request};
+    use axum::http::{header, Extensions, HeaderMap, HeaderValue, StatusCode};
+    use sqlx::PgPool;
+    use std::net::{IpAddr, SocketAddr};
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    #[test]
+    fn refill_after_burst() {
+        let limiter = Limiter::new(RateLimit { per_minute: 60, burst: 2 });
+        let now = Instant::now();
+        assert!(limiter.take(1, now).is_ok());
+        assert!(limiter.take(1, now).is_ok());
+        assert_eq!(limiter.take(1, now), Err(Duration::from_secs(1)));
+        assert!(limiter.take(2, now).is_ok());
+
+        assert!(limiter.take(1, now + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn evict_least_recently_used_beyond_max_keys() {
+        let limiter = Limiter::new(RateLimit { per_minute: 60, burst: 1 });
+        let now = Instant::now();
+        for key in 0..MAX_TRACKED_KEYS + 100 {
+            let _ = limiter.take(key, now);
+            if key == 0 {
+                continue;
+            }
+            // Key 0 stays in use and is never evicted.
+            let _ = limiter.take(0, now);
+        }
+        assert_eq!(limiter.len(), MAX_TRACKED_KEYS);
+        assert!(limiter.take(0, now).is_err());
+        assert!(limiter.take(1, now).is_ok());
+    }
+
+    #[test]
+    fn sweep_refilled_buckets() {
+        let limiter = Limiter::new(RateLimit { per_minute: 60, burst: 2 });
+        let now = Instant::now();
+        let _ = limiter.take(1, now);
+        let _ = limiter.take(2, now + Duration::from_secs(1));
+        limiter.sweep(now + Duration::from_secs(2));
+        assert_eq!(limiter.len(), 1);
+        assert_eq!(limiter.take(2, now + Duration::from_secs(2)), Ok(()));
+        limiter.sweep(now + Duration::from_secs(10));
+        assert_eq!(limiter.len(), 0);
+    }
+
+    #[test]
+    fn client_ip_from_address_appended_by_proxy() {
+        let mut extensions = Extensions::new();
+        extensions.insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234))));
+        let mut headers = HeaderMap::new();
+        // The client sent its own header, which the proxy appended the real address to.
+        headers.insert("X-Forwarded-For", HeaderValue::from_static("203.0.113.7, 198.51.100.2"));
+        assert_eq!(client_ip(&headers, &extensions, true), Some(IpAddr::from([198, 51, 100, 2])));
+        assert_eq!(client_ip(&headers, &extensions, false), Some(IpAddr::from([10, 0, 0, 1])));
+
+        headers.append("X-Forwarded-For", HeaderValue::from_static("192.0.2.9"));
+        assert_eq!(client_ip(&headers, &extensions, true), Some(IpAddr::from([192, 0, 2, 9])));
+    }
+
+    #[sqlx::test]
+    async fn spoofed_forwarded_for_shares_proxy_appended_limit(pool: PgPool) -> TestResult {
+        let app = app(AppState {
+            rate_limits: RateLimits::new(RateLimitConfig {
+                trust_forwarded_for: true,
+                ip: RateLimit { per_minute: 1, burst: 1 },
+                ..Default::default()
+            }),
+            ..test_state(pool)
+        });
+        let request = |spoofed: &str| Request::get("/user_id")
+            .header("X-Forwarded-For", format!("{spoofed}, 198.51.100.2"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request("203.0.113.7")).await?;
+        assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = app.oneshot(request("203.0.113.8")).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_room_from_path() {
+        assert_eq!(room_user_id("/git/12/repo.git/info/refs"), Some(12));
+        assert_eq!(room_user_id("/shares"), None);
+    }
+
+    fn strict_state(pool: PgPool) -> AppState {
+        let limit = RateLimit { per_minute: 1, burst: 1 };
+        AppState {
+            rate_limits: RateLimits::new(RateLimitConfig {
+                user: limit,
+                room: limit,
+                ..Default::default()
+            }),
+            ..test_state(pool)
+        }
+    }
+
+    #[sqlx::test]
+    async fn too_many_requests_per_room(pool: PgPool) -> TestResult {
+        let app = app(strict_state(pool));
+        let request = || Request::get("/git/1/repo.git/info/refs").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request()).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = app.oneshot(request()).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn too_many_requests_per_user(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let app = app(strict_state(pool));
+        let request = || Request::get("/user_id")
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request()).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.oneshot(request()).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn too_many_oauth_requests_per_ip(pool: PgPool) -> TestResult {
+        let app = app(test_state(pool));
+        let request = |ip: [u8; 4]| Request::post("/oauth2/device/code")
+            .extension(ConnectInfo(SocketAddr::from((ip, 1234))))
+            .body(Body::empty())
+            .unwrap();
+        for _ in 0..RateLimitConfig::default().oauth.burst {
+            let response = app.clone().oneshot(request([10, 0, 0, 1])).await?;
+            assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        }
+        let response = app.clone().oneshot(request([10, 0, 0, 1])).await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = app.clone().oneshot(request([10, 0, 0, 2])).await?;
+        assert_ne!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let response = app.oneshot(Request::get("/healthz").extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 1234)))).body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_limited_if_disabled(pool: PgPool) -> TestResult {
+        let app = app(AppState {
+            rate_limits: RateLimits::new(RateLimitConfig {
+                enabled: false,
+                room: RateLimit { per_minute: 1, burst: 1 },
+                ..Default::default()
+            }),
+            ..test_state(pool)
+        });
+        for _ in 0..3 {
+            let response = app.clone().oneshot(Request::get("/git/1/repo.git/info/refs").body(Body::empty())?).await?;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+        Ok(())
+    }
+}
@@ -1,19 +1,55 @@
-use serde::{Deserialize, Serialize};
+use crate::error::ServerError;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::RequestPartsExt;
+use axum_extra::headers::authorization::Bearer;
+use axum_extra::headers::Authorization;
+use axum_extra::TypedHeader;
 use sqlx::types::Uuid;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize, Default)]
-pub struct SessionToken(pub Uuid);
+/// The prefix of every issued session token, which lets secret scanners recognize leaked ones.
+pub const SESSION_TOKEN_PREFIX: &str = "gph_";
+
+/// A session token as presented by the client. Only its keyed hash is ever stored.
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub struct SessionToken(pub String);
 
 impl Display for SessionToken {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_fmt(format_args!("{}", self.0))
+        f.write_str(&self.0)
     }
 }
 
 impl SessionToken {
-    #[cfg(test)]
-    pub(crate) fn max() -> Self {
-        Self(Uuid::max())
+    pub fn generate() -> Self {
+        Self(format!("{SESSION_TOKEN_PREFIX}{}", Uuid::new_v4().simple()))
+    }
+
+    /// Tokens issued before the prefix was introduced are plain UUIDs and are still accepted.
+    fn is_well_formed(token: &str) -> bool {
+        token.strip_prefix(SESSION_TOKEN_PREFIX).is_some_and(|random| !random.is_empty())
+            || Uuid::from_str(token).is_ok()
     }
-}
\ No newline at end of file
+}
+
+#[async_trait::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for SessionToken {
+    type Rejection = ServerError;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) = parts
+            .extract::<TypedHeader<Authorization<Bearer>>>()
+            .await
+            .map_err(|_| ServerError::RequiredSessionToken)?;
+        if !SessionToken::is_well_formed(bearer.token()) {
+            return Err(ServerError::InvalidSessionToken);
+        }
+        Ok(SessionToken(bearer.token().to_string()))
+    }
+}
+
+/// HMAC-SHA256 of a session token, which is what the `sessions` table stores and is looked up by.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SessionTokenHash(pub Vec<u8>);
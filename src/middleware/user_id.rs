@@ -1,16 +1,11 @@
-use crate::db::users::UsersTable;
+use crate::db::sessions::SessionsTable;
 use crate::error::ServerError;
 use crate::middleware::session_token::SessionToken;
 use crate::state::AppState;
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
 use axum::RequestPartsExt;
-use axum_extra::headers::authorization::Bearer;
-use axum_extra::headers::Authorization;
-use axum_extra::TypedHeader;
 use serde::{Deserialize, Serialize};
-use sqlx::types::Uuid;
-use std::str::FromStr;
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Default)]
 pub struct UserId(pub i64);
@@ -26,11 +21,12 @@ impl FromRequestParts<AppState> for UserId {
     type Rejection = ServerError;
 
     async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| ServerError::RequiredSessionToken)?;
-        let session_token = Uuid::from_str(bearer.token()).map_err(|_| ServerError::InvalidSessionToken)?;
-        state.pool.select_from_users(&SessionToken(session_token)).await
+        // Already looked up by the rate limit middleware.
+        if let Some(user_id) = parts.extensions.get::<UserId>() {
+            return Ok(*user_id);
+        }
+        let session_token = parts.extract::<SessionToken>().await?;
+        let token_hash = state.session_key.hash(&session_token);
+        state.pool.select_user_id(&token_hash, &state.session_expiry).await
     }
 }
\ No newline at end of file
@@ -1,8 +1,19 @@
 pub mod oauth2;
+pub mod admin;
 mod git;
 mod user_id;
 mod share;
+mod log;
+mod session;
+mod shares;
+mod health;
+mod usage;
 
 pub use git::git;
+pub use health::{healthz, metrics, readyz};
+pub use log::log;
+pub use session::{delete_session, list_sessions, refresh_session, revoke_session};
 pub use share::share;
+pub use shares::{close_share, get_share, list_shares};
+pub use usage::usage;
 pub use user_id::user_id;
@@ -0,0 +1,230 @@
+use crate::config::ServerConfig;
+use crate::db::admin::{AdminTable, OpenRoom};
+use crate::error::{ServerError, ServerResult};
+use crate::middleware::admin_token::AdminToken;
+use crate::middleware::user_id::UserId;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct BanRequest {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PurgeQuery {
+    /// Defaults to the relay response timeout, after which no guest is waiting for the request.
+    pub older_than_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurgeResult {
+    pub purged: u64,
+}
+
+pub async fn list_rooms(_: AdminToken, State(pool): State<PgPool>) -> ServerResult<Json<Vec<OpenRoom>>> {
+    Ok(Json(pool.select_all_open_rooms().await?))
+}
+
+pub async fn close_room(
+    _: AdminToken,
+    State(pool): State<PgPool>,
+    Path(room_id): Path<Uuid>,
+) -> ServerResult<StatusCode> {
+    if pool.close_room_by_id(room_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ServerError::ShareNotFound)
+    }
+}
+
+pub async fn ban_user(
+    _: AdminToken,
+    State(pool): State<PgPool>,
+    Path(user_id): Path<i64>,
+    Json(request): Json<BanRequest>,
+) -> ServerResult<StatusCode> {
+    if pool.ban_user(UserId(user_id), request.reason.as_deref()).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ServerError::UserNotFound)
+    }
+}
+
+pub async fn unban_user(
+    _: AdminToken,
+    State(pool): State<PgPool>,
+    Path(user_id): Path<i64>,
+) -> ServerResult<StatusCode> {
+    if pool.unban_user(UserId(user_id)).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ServerError::UserNotFound)
+    }
+}
+
+pub async fn purge_requests(
+    _: AdminToken,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<ServerConfig>>,
+    Query(query): Query<PurgeQuery>,
+) -> ServerResult<Json<PurgeResult>> {
+    let older_than = query.older_than_secs.map(Duration::from_secs).unwrap_or(config.relay.response_timeout());
+    let purged = pool.purge_requests(older_than).await?;
+    Ok(Json(PurgeResult { purged }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app;
+    use crate::config::ServerConfig;
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::{DBInit, INSTANCE1, SESSION1};
+    use crate::middleware::user_id::UserId;
+    use crate::route::admin::PurgeResult;
+    use crate::state::AppState;
+    use crate::test::{test_app, test_state, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use axum::Router;
+    use gph_core::types::ShareOptions;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const ADMIN_TOKEN: &str = "admin-token-0123456789abcdef0123456789";
+
+    fn admin_app(pool: PgPool) -> Router {
+        let mut config = ServerConfig::default();
+        config.admin.token = Some(ADMIN_TOKEN.to_string());
+        app(AppState { config: Arc::new(config), ..test_state(pool) })
+    }
+
+    fn admin_request(method: &str, uri: &str, token: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn ban_request(user_id: UserId, body: &'static str) -> Request {
+        Request::put(format!("/admin/users/{}/ban", user_id.0))
+            .header(header::AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn not_found_if_admin_token_not_configured(pool: PgPool) -> TestResult {
+        let response = test_app(pool).await
+            .oneshot(admin_request("GET", "/admin/rooms", ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_invalid_admin_token(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let response = admin_app(pool)
+            .oneshot(admin_request("GET", "/admin/rooms", SESSION1))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn list_and_close_rooms(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let response = admin_app(pool.clone())
+            .oneshot(admin_request("GET", "/admin/rooms", ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let rooms = serde_json::from_slice::<Vec<serde_json::Value>>(&body)?;
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0]["room_id"], room_id.to_string());
+
+        let response = admin_app(pool.clone())
+            .oneshot(admin_request("DELETE", &format!("/admin/rooms/{room_id}"), ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn banned_user_rejected(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let response = admin_app(pool.clone())
+            .oneshot(ban_request(UserId::USER1, r#"{"reason":"spam"}"#))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = admin_app(pool.clone())
+            .oneshot(admin_request("GET", "/user_id", SESSION1))
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = admin_app(pool.clone())
+            .oneshot(admin_request("DELETE", &format!("/admin/users/{}/ban", UserId::USER1.0), ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = admin_app(pool)
+            .oneshot(admin_request("GET", "/user_id", SESSION1))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_ban_unknown_user(pool: PgPool) -> TestResult {
+        let response = admin_app(pool)
+            .oneshot(ban_request(UserId(404), "{}"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_ban_request_malformed(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let response = admin_app(pool.clone())
+            .oneshot(ban_request(UserId::USER1, r#"{"reason":1}"#))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = admin_app(pool.clone())
+            .oneshot(admin_request("PUT", &format!("/admin/users/{}/ban", UserId::USER1.0), ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let response = admin_app(pool)
+            .oneshot(admin_request("GET", "/user_id", SESSION1))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn purge_requests(pool: PgPool) -> TestResult {
+        let response = admin_app(pool)
+            .oneshot(admin_request("DELETE", "/admin/requests?older_than_secs=60", ADMIN_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(serde_json::from_slice::<PurgeResult>(&body)?.purged, 0);
+        Ok(())
+    }
+}
@@ -1,64 +1,267 @@
+use crate::config::ServerConfig;
 use crate::db;
+use crate::db::access_log::{AccessLogTable, NewAccessLog};
 use crate::db::channel::RequestNotify;
 use crate::db::rooms::RoomsTable;
+use crate::db::transfer_usage::{TransferUsageTable, Usage};
 use crate::error::{ServerError, ServerResult};
+use crate::instance::ServerInstance;
+use crate::metrics::METRICS;
+use crate::middleware::rate_limit::client_ip;
 use crate::middleware::user_id::UserId;
-use axum::body::Body;
+use axum::body::{Body, HttpBody};
 use axum::extract::{Path, Request, State};
 use axum::http::{header, HeaderMap, HeaderName, HeaderValue};
 use axum::response::{IntoResponse, Response};
 use futures_util::{pin_mut, StreamExt};
-use http_body_util::BodyExt;
+use gph_core::types::RequestId;
+use http_body_util::{BodyExt, LengthLimitError, Limited};
 use reqwest::StatusCode;
+use sqlx::types::time::OffsetDateTime;
+use sqlx::types::Uuid;
 use sqlx::PgPool;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+
+const UPLOAD_PACK: &str = "git-upload-pack";
+const RECEIVE_PACK: &str = "git-receive-pack";
+
+/// How long the owner took to answer, reported to the guest in `Server-Timing`.
+#[derive(Debug, Clone, Copy)]
+struct OwnerTiming(Duration);
 
 pub async fn git(
     Path((user_id, path)): Path<(i64, String)>,
     State(pool): State<PgPool>,
+    State(config): State<Arc<ServerConfig>>,
+    State(instance): State<ServerInstance>,
     request: Request,
 ) -> Response {
-    let user_id = UserId(user_id);
+    let request_id = RequestId(Uuid::new_v4());
+    let started_at = Instant::now();
+    let span = tracing::info_span!(
+        "relay",
+        request_id = %request_id.0,
+        user_id,
+        method = %request.method(),
+        path = %path,
+    );
+    let mut response = handle_git(&pool, &config, &instance, request_id, UserId(user_id), path, request)
+        .instrument(span)
+        .await;
+    insert_trace_headers(&mut response, request_id, started_at.elapsed());
+    response
+}
+
+async fn handle_git(
+    pool: &PgPool,
+    config: &ServerConfig,
+    instance: &ServerInstance,
+    request_id: RequestId,
+    user_id: UserId,
+    path: String,
+    request: Request,
+) -> Response {
+    if instance.is_shutting_down() {
+        return ServerError::ServerShuttingDown.into_response();
+    }
+    if is_body_too_large(&request, config.relay.max_body_bytes) {
+        return ServerError::PayloadTooLarge.into_response();
+    }
+    let _in_flight = instance.track_request();
     if !pool.is_open_room(user_id).await.is_ok_and(|is_open| is_open) {
         return ServerError::UserRoomIsNotOpen.into_response();
     }
 
-    listen_request(pool, path, user_id, request).await.unwrap_or_else(|e| e.into_response())
+    let started_at = Instant::now();
+    let service = git_service(&path, request.uri().query()).unwrap_or("other");
+    let mut access_log = new_access_log(user_id, &path, &request, config.rate_limit.trust_forwarded_for);
+    let result = relay(pool, config, request_id, path, user_id, request, &mut access_log).await;
+    let relayed = result.is_ok();
+    let response = result.unwrap_or_else(|e| e.into_response());
+    access_log.status = response.status().as_u16();
+    access_log.bytes_out = response.body().size_hint().exact().unwrap_or_default() as i64;
+    METRICS.record_relay(
+        service,
+        access_log.status,
+        started_at.elapsed(),
+        access_log.bytes_in as u64,
+        access_log.bytes_out as u64,
+    );
+    tracing::info!(
+        service,
+        status = access_log.status,
+        bytes_in = access_log.bytes_in,
+        bytes_out = access_log.bytes_out,
+        "Relayed git request",
+    );
+    if let Err(e) = pool.insert_access_log(&access_log).await {
+        tracing::error!("Failed to insert access log({}): {e}", user_id.0);
+    }
+    // The request body was charged against the quota before relaying it, and errors are not the owner's output.
+    if relayed && 0 < access_log.bytes_out {
+        if let Err(e) = pool.add_transfer(user_id, access_log.bytes_out).await {
+            tracing::error!("Failed to add transfer usage({}): {e}", user_id.0);
+        }
+    }
+    response
+}
+
+/// Rejects a body declared larger than the limit before reading it; bodies without `Content-Length`
+/// are cut off while being read instead.
+fn is_body_too_large(request: &Request, max_body_bytes: u64) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_some_and(|content_length| max_body_bytes < content_length)
+}
+
+/// Returns true if either the daily or the monthly quota is used up.
+///
+/// This only saves reading the body of a request that is bound to fail; `TransferUsageTable::charge_transfer`
+/// enforces the quota.
+pub(crate) fn is_quota_exceeded(config: &ServerConfig, usage: Usage) -> bool {
+    let exceeds = |bytes: i64, quota: Option<u64>| quota.is_some_and(|quota| quota <= bytes as u64);
+    exceeds(usage.daily_bytes, config.relay.daily_quota_bytes)
+        || exceeds(usage.monthly_bytes, config.relay.monthly_quota_bytes)
+}
+
+/// Lets guests quote the request id when reporting a problem, and shows where the time went.
+fn insert_trace_headers(response: &mut Response, request_id: RequestId, elapsed: Duration) {
+    let mut server_timing = format!("total;dur={:.1}", elapsed.as_secs_f64() * 1000.);
+    if let Some(OwnerTiming(owner)) = response.extensions().get::<OwnerTiming>() {
+        server_timing.push_str(&format!(", owner;dur={:.1}", owner.as_secs_f64() * 1000.));
+    }
+    let headers = response.headers_mut();
+    headers.insert(HeaderName::from_static("x-request-id"), HeaderValue::from_str(&request_id.0.to_string()).unwrap());
+    headers.insert(HeaderName::from_static("server-timing"), HeaderValue::from_str(&server_timing).unwrap());
+}
+
+async fn relay(
+    pool: &PgPool,
+    config: &ServerConfig,
+    request_id: RequestId,
+    path_info: String,
+    user_id: UserId,
+    request: Request,
+    access_log: &mut NewAccessLog,
+) -> ServerResult<Response> {
+    if is_quota_exceeded(config, pool.select_transfer_usage(user_id).await?) {
+        return Err(ServerError::TransferQuotaExceeded);
+    }
+    let reserved_room = if is_clone_request(request.method().as_str(), &path_info) {
+        Some(pool.reserve_clone(user_id).await?.ok_or(ServerError::ShareUsedUp)?)
+    } else {
+        if access_log.service.as_deref() == Some(UPLOAD_PACK) && pool.is_clone_limit_reached(user_id).await? {
+            return Err(ServerError::ShareUsedUp);
+        }
+        None
+    };
+    let result = listen_request(pool.clone(), config, request_id, path_info, user_id, request, access_log).await;
+    if let Some(room_id) = reserved_room {
+        if !result.as_ref().is_ok_and(|(_, sent_pack)| *sent_pack) {
+            if let Err(e) = pool.release_clone(user_id, room_id).await {
+                tracing::error!("Failed to release clone slot({}): {e}", user_id.0);
+            }
+        }
+    }
+    result.map(|(response, _)| response)
+}
+
+fn new_access_log(user_id: UserId, path_info: &str, request: &Request, trust_forwarded_for: bool) -> NewAccessLog {
+    let remote_addr = client_ip(request.headers(), request.extensions(), trust_forwarded_for).map(|ip| ip.to_string());
+    NewAccessLog {
+        user_id,
+        repository: path_info.split('/').next().unwrap_or_default().to_string(),
+        service: git_service(path_info, request.uri().query()).map(String::from),
+        remote_addr,
+        user_agent: request.headers().get(header::USER_AGENT).and_then(|v| v.to_str().ok()).map(String::from),
+        bytes_in: 0,
+        bytes_out: 0,
+        status: 0,
+        started_at: OffsetDateTime::now_utc(),
+    }
 }
 
 async fn listen_request(
     pool: PgPool,
+    config: &ServerConfig,
+    request_id: RequestId,
     path_info: String,
     user_id: UserId,
     request: Request,
-) -> ServerResult<Response> {
-    let mut request_notify = RequestNotify {
+    access_log: &mut NewAccessLog,
+) -> ServerResult<(Response, bool)> {
+    let request_notify = RequestNotify {
         to: user_id,
-        id: Default::default(),
+        id: request_id,
         path_info,
         request_method: request.method().to_string(),
         query_string: request.uri().query().map(String::from),
         content_length: request.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).map(String::from),
         content_type: request.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(String::from),
+        remote_addr: access_log.remote_addr.clone(),
     };
 
-    let Ok(request_body) = request.into_body().collect().await else {
-        return Err(ServerError::FailedParseRequestBody);
+    let max_body_bytes = usize::try_from(config.relay.max_body_bytes).unwrap_or(usize::MAX);
+    let request_body = match Limited::new(request.into_body(), max_body_bytes).collect().await {
+        Ok(request_body) => request_body,
+        Err(e) if e.is::<LengthLimitError>() => return Err(ServerError::PayloadTooLarge),
+        Err(_) => return Err(ServerError::FailedParseRequestBody),
     };
+    let request_body = request_body.to_bytes();
+    let charged = pool
+        .charge_transfer(user_id, request_body.len() as i64, config.relay.daily_quota_bytes, config.relay.monthly_quota_bytes)
+        .await?;
+    if !charged {
+        return Err(ServerError::TransferQuotaExceeded);
+    }
+    access_log.bytes_in = request_body.len() as i64;
 
-    let request_id = db::channel::guest::new_request(&pool, user_id, request_body.to_bytes().as_ref()).await?;
-    request_notify.id = request_id;
+    db::channel::guest::insert_request(&pool, request_id, user_id, request_body.as_ref()).await?;
     let stream = db::channel::guest::listen(pool.clone(), request_id).await?;
     pin_mut!(stream);
 
     db::channel::guest::request_to_owner(&pool, &request_notify).await?;
+    let sent_at = Instant::now();
 
-    let response = stream
-        .next()
+    let response = tokio::time::timeout(config.relay.response_timeout(), stream.next())
         .await
+        .map_err(|_| {
+            tracing::warn!("The owner did not respond within {:?}", config.relay.response_timeout());
+            ServerError::OwnerResponseTimeout
+        })?
         .ok_or(ServerError::FailedRecvGitResponse)?;
+    let owner_timing = OwnerTiming(sent_at.elapsed());
+
+    let sent_pack = is_completed_clone(&request_notify, &response);
+    let mut response = convert_to_response(&response)?;
+    response.extensions_mut().insert(owner_timing);
+    Ok((response, sent_pack))
+}
+
+fn git_service(path_info: &str, query: Option<&str>) -> Option<&'static str> {
+    [UPLOAD_PACK, RECEIVE_PACK].into_iter().find(|service| {
+        path_info.ends_with(&format!("/{service}"))
+            || query.is_some_and(|query| query.split('&').any(|param| param == format!("service={service}")))
+    })
+}
+
+/// A `git-upload-pack` POST, which takes a clone slot since it may be answered with a packfile.
+fn is_clone_request(method: &str, path_info: &str) -> bool {
+    method == "POST" && git_service(path_info, None) == Some(UPLOAD_PACK)
+}
 
-    convert_to_response(&response)
+/// Returns true if the owner responded to `git-upload-pack` with a packfile,
+/// which means that a guest has actually fetched objects.
+fn is_completed_clone(request: &RequestNotify, output: &[u8]) -> bool {
+    const PACK_SIGNATURE: &[u8] = b"PACK\0\0\0\x02";
+    is_clone_request(&request.request_method, &request.path_info)
+        && output.windows(PACK_SIGNATURE.len()).any(|window| window == PACK_SIGNATURE)
 }
 
 fn convert_to_response(output: &[u8]) -> ServerResult<Response> {
@@ -112,13 +315,27 @@ fn header_end_index(output: &[u8]) -> Option<usize> {
 
 #[cfg(test)]
 mod tests {
+    use crate::db;
+    use crate::db::access_log::AccessLogTable;
+    use crate::db::channel::RequestNotify;
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::INSTANCE1;
+    use crate::db::transfer_usage::TransferUsageTable;
     use crate::middleware::user_id::UserId;
-    use crate::route::git::convert_to_response;
-    use crate::test::{test_app, TestResult};
+    use crate::route::git::{convert_to_response, git_service, is_completed_clone, RECEIVE_PACK, UPLOAD_PACK};
+    use crate::app;
+    use crate::config::ServerConfig;
+    use crate::instance::ServerInstance;
+    use crate::state::AppState;
+    use crate::test::{test_app, test_state, TestResult};
+    use gph_core::types::ShareOptions;
     use axum::body::Body;
     use axum::extract::Request;
     use axum::http::StatusCode;
+    use futures_util::{pin_mut, StreamExt};
+    use sqlx::types::Uuid;
     use sqlx::PgPool;
+    use std::sync::Arc;
     use tower::ServiceExt;
 
     #[test]
@@ -145,6 +362,247 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test]
+    async fn trace_headers_if_room_closed(pool: PgPool) -> TestResult {
+        let response = test_app(pool).await
+            .oneshot(git_request(UserId::USER1, "sample.git", "/info/refs"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Uuid::parse_str(response.headers()["x-request-id"].to_str()?)?;
+        assert!(response.headers()["server-timing"].to_str()?.starts_with("total;dur="));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn request_id_sent_to_owner_and_guest(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let requests = db::channel::owner::listen(pool.clone(), UserId::USER1).await?;
+        let owner = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pin_mut!(requests);
+                let git_request = requests.next().await.unwrap();
+                db::channel::owner::response(&pool, &git_request.id, b"Status: 200 OK\r\n\r\nok").await.unwrap();
+                git_request.id
+            }
+        });
+
+        let response = test_app(pool).await
+            .oneshot(git_request(UserId::USER1, "sample.git", "/info/refs"))
+            .await?;
+        let request_id = owner.await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-request-id"].to_str()?, request_id.0.to_string());
+        assert!(response.headers()["server-timing"].to_str()?.contains(", owner;dur="));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_clone_limit_reached(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(1), ..Default::default() }).await?;
+        pool.reserve_clone(UserId::USER1).await?;
+        let app = test_app(pool).await;
+        let response = app
+            .oneshot(git_request(UserId::USER1, "sample.git", "/info/refs?service=git-upload-pack"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::GONE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn access_log_recorded_if_room_open(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(0), ..Default::default() }).await?;
+        let app = test_app(pool.clone()).await;
+        app.oneshot(git_request(UserId::USER1, "sample.git", "/info/refs?service=git-upload-pack")).await?;
+        let logs = pool.select_access_log(UserId::USER1, 10).await?;
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].repository, "sample.git");
+        assert_eq!(logs[0].service.as_deref(), Some(UPLOAD_PACK));
+        assert_eq!(logs[0].status, StatusCode::GONE.as_u16());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn forwarded_for_ignored_unless_trusted(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(0), ..Default::default() }).await?;
+        let request = || Request::get(format!("/git/{}/sample.git/info/refs?service=git-upload-pack", UserId::USER1.0))
+            .header("X-Forwarded-For", "192.0.2.1, 203.0.113.7")
+            .body(Body::empty());
+        test_app(pool.clone()).await.oneshot(request()?).await?;
+
+        let mut config = ServerConfig::default();
+        config.rate_limit.trust_forwarded_for = true;
+        app(AppState { config: Arc::new(config), ..test_state(pool.clone()) }).oneshot(request()?).await?;
+
+        let logs = pool.select_access_log(UserId::USER1, 10).await?;
+        let mut remote_addrs = logs.iter().map(|log| log.remote_addr.as_deref()).collect::<Vec<_>>();
+        remote_addrs.sort();
+        assert_eq!(remote_addrs, vec![None, Some("203.0.113.7")]);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_shutting_down(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let instance = ServerInstance::new();
+        instance.shutdown();
+        let app = app(AppState { instance, ..test_state(pool) });
+        let response = app
+            .oneshot(git_request(UserId::USER1, "sample.git", "/info/refs?service=git-upload-pack"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_content_length_too_large(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let app = app(AppState { config: Arc::new(relay_config(10, None)), ..test_state(pool.clone()) });
+        let response = app
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-receive-pack", UserId::USER1.0))
+                .header("content-length", "11")
+                .body(Body::from("01234567890"))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(pool.select_access_log(UserId::USER1, 10).await?.is_empty());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_streamed_body_too_large(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let app = app(AppState { config: Arc::new(relay_config(10, None)), ..test_state(pool) });
+        let chunks = futures_util::stream::iter(["012345", "67890"].map(Ok::<_, std::io::Error>));
+        let response = app
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-receive-pack", UserId::USER1.0))
+                .body(Body::from_stream(chunks))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_quota_exceeded(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.add_transfer(UserId::USER1, 100).await?;
+        let app = app(AppState { config: Arc::new(relay_config(1024, Some(100))), ..test_state(pool) });
+        let response = app
+            .oneshot(git_request(UserId::USER1, "sample.git", "/info/refs?service=git-upload-pack"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_request_body_exceeds_quota(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        pool.add_transfer(UserId::USER1, 90).await?;
+        let app = app(AppState { config: Arc::new(relay_config(1024, Some(100))), ..test_state(pool.clone()) });
+        let response = app
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-receive-pack", UserId::USER1.0))
+                .body(Body::from("0123456789a"))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(pool.select_transfer_usage(UserId::USER1).await?.daily_bytes, 90);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn clone_slot_released_if_no_pack(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(1), ..Default::default() }).await?;
+        let requests = db::channel::owner::listen(pool.clone(), UserId::USER1).await?;
+        let owner = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pin_mut!(requests);
+                let git_request = requests.next().await.unwrap();
+                db::channel::owner::response(&pool, &git_request.id, b"Status: 200 OK\r\n\r\n0008NAK\n").await.unwrap();
+            }
+        });
+
+        let response = test_app(pool.clone()).await
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-upload-pack", UserId::USER1.0))
+                .body(Body::from("0000"))?)
+            .await?;
+        owner.await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!pool.is_clone_limit_reached(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_no_clone_slot_left(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions { max_clones: Some(1), ..Default::default() }).await?;
+        pool.reserve_clone(UserId::USER1).await?;
+        let response = test_app(pool).await
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-upload-pack", UserId::USER1.0))
+                .body(Body::from("0000"))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::GONE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn transfer_usage_recorded(pool: PgPool) -> TestResult {
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let requests = db::channel::owner::listen(pool.clone(), UserId::USER1).await?;
+        let owner = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                pin_mut!(requests);
+                let git_request = requests.next().await.unwrap();
+                db::channel::owner::response(&pool, &git_request.id, b"Status: 200 OK\r\n\r\nok").await.unwrap();
+            }
+        });
+
+        let response = test_app(pool.clone()).await
+            .oneshot(Request::post(format!("/git/{}/sample.git/git-upload-pack", UserId::USER1.0))
+                .body(Body::from("0000"))?)
+            .await?;
+        owner.await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let usage = pool.select_transfer_usage(UserId::USER1).await?;
+        assert_eq!(usage.daily_bytes, 6);
+        assert_eq!(usage.monthly_bytes, 6);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn access_log_not_recorded_if_room_closed(pool: PgPool) -> TestResult {
+        let app = test_app(pool.clone()).await;
+        app.oneshot(git_request(UserId::USER1, "sample.git", "/info/refs?service=git-upload-pack")).await?;
+        let logs = pool.select_access_log(UserId::USER1, 10).await?;
+        assert!(logs.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn detect_git_service() {
+        assert_eq!(git_service("sample.git/info/refs", Some("service=git-upload-pack")), Some(UPLOAD_PACK));
+        assert_eq!(git_service("sample.git/git-upload-pack", None), Some(UPLOAD_PACK));
+        assert_eq!(git_service("sample.git/info/refs", Some("service=git-receive-pack")), Some(RECEIVE_PACK));
+        assert_eq!(git_service("sample.git/git-receive-pack", None), Some(RECEIVE_PACK));
+        assert_eq!(git_service("sample.git/HEAD", None), None);
+    }
+
+    #[test]
+    fn completed_clone_if_response_contains_pack() {
+        let request = RequestNotify {
+            path_info: "sample.git/git-upload-pack".to_string(),
+            request_method: "POST".to_string(),
+            ..Default::default()
+        };
+        assert!(is_completed_clone(&request, b"0008NAK\nPACK\0\0\0\x02\0\0\0\x03"));
+        assert!(!is_completed_clone(&request, b"0008NAK\n"));
+    }
+
+    fn relay_config(max_body_bytes: u64, daily_quota_bytes: Option<u64>) -> ServerConfig {
+        let mut config = ServerConfig::default();
+        config.relay.max_body_bytes = max_body_bytes;
+        config.relay.daily_quota_bytes = daily_quota_bytes;
+        config
+    }
+
     fn git_request(user_id: UserId, repository: &str, path: &str) -> Request {
         Request::get(format!("/git/{}/{repository}{path}", user_id.0)).body(Body::empty()).unwrap()
     }
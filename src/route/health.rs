@@ -0,0 +1,160 @@
+use crate::db::rooms::RoomsTable;
+use crate::error::ServerResult;
+use crate::instance::ServerInstance;
+use crate::metrics::{Gauges, METRICS};
+use crate::middleware::admin_token::MetricsToken;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use sqlx::PgPool;
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// Readiness: the database is reachable and the server is not shutting down.
+pub async fn readyz(
+    State(pool): State<PgPool>,
+    State(instance): State<ServerInstance>,
+) -> (StatusCode, &'static str) {
+    if instance.is_shutting_down() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shutting down");
+    }
+    match sqlx::query("SELECT 1").execute(&pool).await {
+        Ok(_) => (StatusCode::OK, "ok"),
+        Err(e) => {
+            tracing::error!("Readiness check failed: {e}");
+            (StatusCode::SERVICE_UNAVAILABLE, "database unavailable")
+        }
+    }
+}
+
+pub async fn metrics(
+    _: MetricsToken,
+    State(pool): State<PgPool>,
+    State(instance): State<ServerInstance>,
+) -> ServerResult<impl IntoResponse> {
+    let gauges = Gauges {
+        open_rooms: pool.count_open_rooms().await?,
+        connected_owners: instance.connected_owners(),
+    };
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        METRICS.render(gauges),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::app;
+    use crate::config::ServerConfig;
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::{DBInit, INSTANCE1};
+    use crate::instance::ServerInstance;
+    use crate::middleware::user_id::UserId;
+    use crate::state::AppState;
+    use crate::test::{test_app, test_state, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use axum::Router;
+    use gph_core::types::ShareOptions;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    const METRICS_TOKEN: &str = "metrics-token-0123456789abcdef0123456789";
+
+    fn metrics_app(pool: PgPool) -> Router {
+        let mut config = ServerConfig::default();
+        config.admin.metrics_token = Some(METRICS_TOKEN.to_string());
+        app(AppState { config: Arc::new(config), ..test_state(pool) })
+    }
+
+    fn metrics_request(token: &str) -> Request {
+        Request::get("/metrics")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn ok_healthz(pool: PgPool) -> TestResult {
+        let response = test_app(pool).await
+            .oneshot(Request::get("/healthz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_readyz(pool: PgPool) -> TestResult {
+        let response = test_app(pool).await
+            .oneshot(Request::get("/readyz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_ready_if_database_closed(pool: PgPool) -> TestResult {
+        let app = test_app(pool.clone()).await;
+        pool.close().await;
+        let response = app
+            .oneshot(Request::get("/readyz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn not_ready_if_shutting_down(pool: PgPool) -> TestResult {
+        let instance = ServerInstance::new();
+        instance.shutdown();
+        let response = app(AppState { instance, ..test_state(pool) })
+            .oneshot(Request::get("/readyz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn metrics_report_open_rooms(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let response = metrics_app(pool)
+            .oneshot(metrics_request(METRICS_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let text = String::from_utf8(body.to_vec())?;
+        assert!(text.contains("gph_open_rooms 1\n"));
+        assert!(text.contains("gph_connected_owners 0\n"));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn metrics_not_found_if_token_not_configured(pool: PgPool) -> TestResult {
+        let response = test_app(pool).await
+            .oneshot(metrics_request(METRICS_TOKEN))
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_invalid_metrics_token(pool: PgPool) -> TestResult {
+        let app = metrics_app(pool);
+        let response = app.clone()
+            .oneshot(metrics_request("metrics-token-wrong"))
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app
+            .oneshot(Request::get("/metrics").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use crate::db::access_log::AccessLogTable;
+use crate::error::ServerResult;
+use crate::middleware::user_id::UserId;
+use axum::extract::{Query, State};
+use axum::Json;
+use gph_core::types::AccessLogEntry;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct LogQuery {
+    pub limit: Option<i64>,
+}
+
+pub async fn log(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    Query(query): Query<LogQuery>,
+) -> ServerResult<Json<Vec<AccessLogEntry>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let logs = pool.select_access_log(user_id, limit).await?;
+    Ok(Json(logs))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::access_log::tests::access_log;
+    use crate::db::access_log::AccessLogTable;
+    use crate::db::test::{DBInit, SESSION1};
+    use crate::middleware::user_id::UserId;
+    use crate::test::{test_app, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use gph_core::types::AccessLogEntry;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn err_if_missing_session_token(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let response = app.oneshot(Request::get("/log").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_fetch_access_log(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.insert_access_log(&access_log(UserId::USER1)).await?;
+        let app = test_app(pool).await;
+        let response = app
+            .oneshot(Request::get("/log")
+                .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+                .body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let logs = serde_json::from_slice::<Vec<AccessLogEntry>>(&body)?;
+        assert_eq!(logs.len(), 1);
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 mod auth;
+mod device;
 mod register;
 
 pub use auth::auth;
+pub use device::{device_code, device_token};
 pub use register::register;
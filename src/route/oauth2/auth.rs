@@ -1,34 +1,71 @@
-use crate::state::GithubCredentials;
-use axum::extract::State;
+use crate::db::oauth_states::{OAuthState, OAuthStatesTable};
+use crate::error::{ServerError, ServerResult};
+use crate::identity::{authorize_url, IdentityProviders};
+use axum::extract::{Query, State};
 use axum::response::{IntoResponse, Redirect, Response};
-use oauth2::basic::BasicClient;
-use oauth2::{AuthUrl, CsrfToken, TokenUrl};
+use oauth2::{PkceCodeChallenge, RedirectUrl};
+use serde::Deserialize;
+use sqlx::PgPool;
 
+#[derive(Deserialize, Debug)]
+pub struct AuthQuery {
+    /// The identity provider to log in with; the server's default one if omitted.
+    provider: Option<String>,
+    /// The CLI's loopback callback. The provider's registered callback is used if omitted.
+    redirect_uri: Option<String>,
+    /// A random value the client keeps to itself until it calls `/oauth2/register`.
+    nonce: Option<String>,
+}
 
 pub async fn auth(
-    State(credential): State<GithubCredentials>
-) -> Response {
-    let (auth_url, _csrf_token) = oauth_client(credential.clone())
-        .authorize_url(CsrfToken::new_random)
-        .url();
-    Redirect::to(auth_url.as_ref()).into_response()
+    Query(query): Query<AuthQuery>,
+    State(pool): State<PgPool>,
+    State(identity_providers): State<IdentityProviders>,
+) -> ServerResult<Response> {
+    let nonce = query.nonce.filter(|nonce| is_valid_nonce(nonce)).ok_or(ServerError::InvalidLoginNonce)?;
+    let provider = identity_providers.get(query.provider.as_deref())?;
+    let redirect_url = query.redirect_uri.map(loopback_redirect_url).transpose()?;
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let (auth_url, csrf_token) = authorize_url(provider.as_ref(), redirect_url.clone(), pkce_challenge);
+    pool.insert_oauth_state(csrf_token.secret(), &OAuthState {
+        provider: provider.name().to_string(),
+        pkce_verifier: pkce_verifier.secret().clone(),
+        redirect_uri: redirect_url.map(|url| url.to_string()),
+        nonce: Some(nonce),
+    }).await?;
+    Ok(Redirect::to(auth_url.as_ref()).into_response())
 }
 
-fn oauth_client(credential: GithubCredentials) -> BasicClient {
-    BasicClient::new(
-        credential.client_id,
-        Some(credential.client_secret),
-        AuthUrl::new("https://github.com/login/oauth/authorize".to_string()).unwrap(),
-        Some(TokenUrl::new("https://github.com/login/oauth/access_token".to_string()).unwrap()),
-    )
+/// Long enough not to be guessed, and kept to characters that need no escaping in a query.
+fn is_valid_nonce(nonce: &str) -> bool {
+    (32..=128).contains(&nonce.len())
+        && nonce.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Only plain http callbacks on the loopback interface are accepted,
+/// so that a login can never be redirected to another host.
+fn loopback_redirect_url(redirect_uri: String) -> ServerResult<RedirectUrl> {
+    let url = RedirectUrl::new(redirect_uri).map_err(|_| ServerError::InvalidRedirectUri)?;
+    let is_loopback = matches!(
+        url.url().host_str(),
+        Some("127.0.0.1" | "[::1]" | "localhost")
+    );
+    if url.url().scheme() != "http" || !is_loopback {
+        return Err(ServerError::InvalidRedirectUri);
+    }
+    Ok(url)
 }
 
 
 #[cfg(test)]
 mod tests {
-    use crate::test::{auth_request, test_app, TestResult};
-    use axum::http::StatusCode;
+    use crate::db::oauth_states::OAuthStatesTable;
+    use crate::test::{auth_request, test_app, TestResult, NONCE};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
     use sqlx::PgPool;
+    use std::collections::HashMap;
     use tower::ServiceExt;
 
     #[sqlx::test]
@@ -38,4 +75,81 @@ mod tests {
         assert_eq!(response.status(), StatusCode::SEE_OTHER);
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[sqlx::test]
+    async fn redirect_with_persisted_state_and_pkce(pool: PgPool) -> TestResult {
+        let app = test_app(pool.clone()).await;
+        let response = app.oneshot(auth_request()).await?;
+        let query = location_query(response.headers()[header::LOCATION].to_str()?)?;
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(query.contains_key("code_challenge"));
+        pool.take_oauth_state(&query["state"]).await?;
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn redirect_to_loopback_callback(pool: PgPool) -> TestResult {
+        let app = test_app(pool.clone()).await;
+        let redirect_uri = "http://127.0.0.1:54321/oauth2/callback";
+        let request = Request::get(format!("/oauth2/auth?nonce={NONCE}&redirect_uri={redirect_uri}")).body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let query = location_query(response.headers()[header::LOCATION].to_str()?)?;
+        assert_eq!(query["redirect_uri"], redirect_uri);
+        let oauth_state = pool.take_oauth_state(&query["state"]).await?;
+        assert_eq!(oauth_state.redirect_uri.as_deref(), Some(redirect_uri));
+        assert_eq!(oauth_state.nonce.as_deref(), Some(NONCE));
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_nonce_missing_or_invalid(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        for query in ["", "?nonce=short", "?nonce=0123456789abcdef0123456789abcdef%26state%3Dx"] {
+            let request = Request::get(format!("/oauth2/auth{query}")).body(Body::empty())?;
+            let response = app.clone().oneshot(request).await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_redirect_uri_is_not_loopback(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        for redirect_uri in ["http://example.com/oauth2/callback", "https://127.0.0.1/oauth2/callback", "invalid"] {
+            let request = Request::get(format!("/oauth2/auth?nonce={NONCE}&redirect_uri={redirect_uri}")).body(Body::empty())?;
+            let response = app.clone().oneshot(request).await?;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn redirect_to_chosen_provider(pool: PgPool) -> TestResult {
+        let app = test_app(pool.clone()).await;
+        let request = Request::get(format!("/oauth2/auth?nonce={NONCE}&provider=gitlab")).body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        let location = response.headers()[header::LOCATION].to_str()?;
+        assert!(location.starts_with("https://gitlab.example.com/oauth/authorize?"));
+        let query = location_query(location)?;
+        assert_eq!(query["scope"], "read_user");
+        assert_eq!(pool.take_oauth_state(&query["state"]).await?.provider, "gitlab");
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_unknown_provider(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let request = Request::get(format!("/oauth2/auth?nonce={NONCE}&provider=unknown")).body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    fn location_query(location: &str) -> TestResult<HashMap<String, String>> {
+        Ok(reqwest::Url::parse(location)?
+            .query_pairs()
+            .into_owned()
+            .collect())
+    }
+}
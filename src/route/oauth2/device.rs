@@ -0,0 +1,72 @@
+use crate::error::ServerResult;
+use crate::identity::{poll_device_token, request_device_code, IdentityProviders};
+use crate::route::oauth2::register::sign_in;
+use crate::state::SessionKey;
+use axum::extract::{Query, State};
+use axum::Json;
+use gph_core::types::{DeviceAuthorization, DeviceTokenRequest, DeviceTokenResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+#[derive(Deserialize, Debug)]
+pub struct DeviceCodeQuery {
+    provider: Option<String>,
+}
+
+/// Starts a device-code login, for machines that cannot open a browser or receive a callback.
+pub async fn device_code(
+    Query(query): Query<DeviceCodeQuery>,
+    State(identity_providers): State<IdentityProviders>,
+) -> ServerResult<Json<DeviceAuthorization>> {
+    let provider = identity_providers.get(query.provider.as_deref())?;
+    Ok(Json(request_device_code(provider.as_ref()).await?))
+}
+
+/// Checks once whether the user has entered the code, and signs in if so.
+pub async fn device_token(
+    State(pool): State<PgPool>,
+    State(identity_providers): State<IdentityProviders>,
+    State(session_key): State<SessionKey>,
+    Json(request): Json<DeviceTokenRequest>,
+) -> ServerResult<Json<DeviceTokenResponse>> {
+    let provider = identity_providers.get(request.provider.as_deref())?;
+    let access_token = match poll_device_token(provider.as_ref(), &request.device_code).await? {
+        Ok(access_token) => access_token,
+        Err(pending) => return Ok(Json(pending)),
+    };
+    let device_name = request.device_name.as_deref();
+    let session_token = sign_in(&pool, &session_key, provider.as_ref(), &access_token, device_name).await?;
+    Ok(Json(DeviceTokenResponse::Complete {
+        session_token: session_token.to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::{test_app, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::StatusCode;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn err_if_device_code_is_not_set(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let request = Request::post("/oauth2/device/token")
+            .header("content-type", "application/json")
+            .body(Body::from("{}"))?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_unknown_provider(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let request = Request::post("/oauth2/device/code?provider=unknown").body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
+use crate::db::oauth_states::OAuthStatesTable;
+use crate::db::sessions::SessionsTable;
 use crate::db::users::UsersTable;
 use crate::error::{ServerError, ServerResult};
-use crate::middleware::user_id::UserId;
-use crate::state::GithubCredentials;
+use crate::identity::{exchange_code, IdentityProvider, IdentityProviders};
+use crate::middleware::session_token::SessionToken;
+use crate::state::SessionKey;
 use axum::extract::{Query, State};
-use axum::http::header;
-use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
 use std::collections::HashMap;
 
@@ -12,74 +13,112 @@ use std::collections::HashMap;
 pub async fn register(
     Query(query): Query<HashMap<String, String>>,
     State(pool): State<PgPool>,
-    State(github_credentials): State<GithubCredentials>,
+    State(identity_providers): State<IdentityProviders>,
+    State(session_key): State<SessionKey>,
 ) -> ServerResult<String> {
     let Some(auth_code) = query.get("code") else {
         return Err(ServerError::MissingAuthCode);
     };
-    let access_token = fetch_access_token(&github_credentials, auth_code)
-        .await
-        .map_err(|_| ServerError::FailedConnectGithubApi)?
-        .access_token;
-    let user_id = fetch_github_id(&access_token).await?;
-    let session_token = pool.insert_into_users(&user_id).await?;
+    let Some(state) = query.get("state") else {
+        return Err(ServerError::InvalidOAuthState);
+    };
+    let oauth_state = pool.take_oauth_state(state).await?;
+    // The state alone only proves that someone started this login, not that this client did.
+    if oauth_state.nonce.is_none() || oauth_state.nonce.as_ref() != query.get("nonce") {
+        return Err(ServerError::InvalidOAuthState);
+    }
+    let provider = identity_providers.get(Some(&oauth_state.provider))?;
+    let access_token = exchange_code(provider.as_ref(), auth_code, &oauth_state).await?;
+    let device_name = query.get("device").map(String::as_str);
+    let session_token = sign_in(&pool, &session_key, provider.as_ref(), &access_token, device_name).await?;
     Ok(session_token.to_string())
 }
 
-#[derive(Deserialize, Serialize, Eq, PartialEq, Debug)]
-pub struct AccessTokenResponse {
-    pub access_token: String,
-    pub scope: String,
-    pub token_type: String,
-}
-
-async fn fetch_access_token(credential: &GithubCredentials, code: &str) -> reqwest::Result<AccessTokenResponse> {
-    reqwest::Client::new()
-        .post("https://github.com/login/oauth/access_token")
-        .header(header::ACCEPT, "application/json")
-        .json(&serde_json::json!({
-            "client_id": credential.client_id,
-            "client_secret": credential.client_secret,
-            "code": code,
-        }))
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await
-}
-
-async fn fetch_github_id(access_token: &str) -> ServerResult<UserId> {
-    let json = reqwest::Client::new()
-        .get("https://api.github.com/user")
-        .header(header::USER_AGENT, "meltos_app")
-        .header(header::ACCEPT, "application/vnd.github+json")
-        .header("X-GitHub-Api-Version", "2022-11-28")
-        .bearer_auth(access_token)
-        .send()
-        .await
-        .and_then(|response| response.error_for_status())
-        .map_err(|_| ServerError::FailedConnectGithubApi)?
-        .json::<HashMap<String, serde_json::Value>>()
-        .await
-        .map_err(|_| ServerError::FailedConnectGithubApi)?;
-    let user_id = json
-        .get("id")
-        .ok_or_else(|| ServerError::FailedConnectGithubApi)?
-        .as_i64()
-        .ok_or_else(|| ServerError::FailedConnectGithubApi)?;
-    Ok(UserId(user_id))
+/// Starts a new session for the user who owns `access_token`, unless the user is banned.
+pub(super) async fn sign_in(
+    pool: &PgPool,
+    session_key: &SessionKey,
+    provider: &dyn IdentityProvider,
+    access_token: &str,
+    device_name: Option<&str>,
+) -> ServerResult<SessionToken> {
+    let subject = provider.fetch_subject(access_token).await?;
+    let user_id = pool.select_or_insert_user(provider.name(), &subject).await?;
+    if pool.is_banned(user_id).await? {
+        return Err(ServerError::UserBanned);
+    }
+    let session_token = SessionToken::generate();
+    pool.insert_session(&user_id, device_name, &session_key.hash(&session_token)).await?;
+    Ok(session_token)
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::db::admin::AdminTable;
+    use crate::db::oauth_states::{OAuthState, OAuthStatesTable};
+    use crate::db::users::UsersTable;
+    use crate::error::{ServerError, ServerResult};
+    use crate::identity::{IdentityProvider, ProviderEndpoints};
+    use crate::route::oauth2::register::sign_in;
+    use crate::state::SessionKey;
     use crate::test::{test_app, TestResult};
+    use async_trait::async_trait;
     use axum::body::Body;
     use axum::extract::Request;
     use axum::http::StatusCode;
+    use http_body_util::BodyExt;
+    use oauth2::{AuthUrl, ClientId, ClientSecret, TokenUrl};
     use sqlx::PgPool;
     use tower::ServiceExt;
 
+    /// Answers every access token with the same subject, without calling a server.
+    struct StubProvider(ProviderEndpoints);
+
+    impl StubProvider {
+        fn new() -> Self {
+            Self(ProviderEndpoints {
+                client_id: ClientId::new("client".to_string()),
+                client_secret: ClientSecret::new("secret".to_string()),
+                auth_url: AuthUrl::new("http://localhost/auth".to_string()).unwrap(),
+                token_url: TokenUrl::new("http://localhost/token".to_string()).unwrap(),
+                device_authorization_url: None,
+                scopes: Vec::new(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl IdentityProvider for StubProvider {
+        fn name(&self) -> &str {
+            "stub"
+        }
+
+        fn endpoints(&self) -> &ProviderEndpoints {
+            &self.0
+        }
+
+        async fn fetch_subject(&self, _access_token: &str) -> ServerResult<String> {
+            Ok("1".to_string())
+        }
+    }
+
+    #[sqlx::test]
+    async fn err_if_user_banned(pool: PgPool) -> TestResult {
+        let user_id = pool.select_or_insert_user("stub", "1").await?;
+        pool.ban_user(user_id, Some("spam")).await?;
+        let result = sign_in(&pool, &SessionKey::test(), &StubProvider::new(), "token", None).await;
+        assert!(matches!(result, Err(ServerError::UserBanned)));
+        let sessions: i64 = sqlx::query_scalar("SELECT count(*) FROM sessions WHERE user_id=$1")
+            .bind(user_id.0)
+            .fetch_one(&pool)
+            .await?;
+        assert_eq!(sessions, 0);
+
+        pool.unban_user(user_id).await?;
+        assert!(sign_in(&pool, &SessionKey::test(), &StubProvider::new(), "token", None).await.is_ok());
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn err_if_code_is_not_set(pool: PgPool) -> TestResult {
         let app = test_app(pool).await;
@@ -87,4 +126,50 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
         Ok(())
     }
+
+    #[sqlx::test]
+    async fn err_if_state_is_not_set(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let response = app.oneshot(Request::put("/oauth2/register?code=code").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), ServerError::InvalidOAuthState.to_string().as_bytes());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_nonce_does_not_match(pool: PgPool) -> TestResult {
+        pool.insert_oauth_state("state", &OAuthState {
+            provider: "github".to_string(),
+            pkce_verifier: "verifier".to_string(),
+            redirect_uri: None,
+            nonce: Some("attacker".to_string()),
+        }).await?;
+        let app = test_app(pool.clone()).await;
+        let request = Request::put("/oauth2/register?code=code&state=state&nonce=victim").body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), ServerError::InvalidOAuthState.to_string().as_bytes());
+        // The attempt is consumed, so the state cannot be tried with other nonces.
+        assert!(pool.take_oauth_state("state").await.is_err());
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_if_state_is_unknown(pool: PgPool) -> TestResult {
+        pool.insert_oauth_state("state", &OAuthState {
+            provider: "github".to_string(),
+            pkce_verifier: "verifier".to_string(),
+            redirect_uri: None,
+            nonce: Some("nonce".to_string()),
+        }).await?;
+        let app = test_app(pool).await;
+        let request = Request::put("/oauth2/register?code=code&state=other&nonce=nonce").body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), ServerError::InvalidOAuthState.to_string().as_bytes());
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+use crate::db::sessions::SessionsTable;
+use crate::error::{ServerError, ServerResult};
+use crate::middleware::session_token::SessionToken;
+use crate::middleware::user_id::UserId;
+use crate::state::SessionKey;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use gph_core::types::SessionInfo;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+
+pub async fn delete_session(
+    session_token: SessionToken,
+    State(pool): State<PgPool>,
+    State(key): State<SessionKey>,
+) -> ServerResult<StatusCode> {
+    pool.revoke_session_token(&key.hash(&session_token)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Issues a new session token in exchange for the current one, which must not be expired yet.
+/// The session's absolute expiry still counts from when it was first issued.
+pub async fn refresh_session(
+    _: UserId,
+    session_token: SessionToken,
+    State(pool): State<PgPool>,
+    State(key): State<SessionKey>,
+) -> ServerResult<String> {
+    let new_token = SessionToken::generate();
+    pool.rotate_session_token(&key.hash(&session_token), &key.hash(&new_token)).await?;
+    Ok(new_token.to_string())
+}
+
+pub async fn list_sessions(
+    user_id: UserId,
+    session_token: SessionToken,
+    State(pool): State<PgPool>,
+    State(key): State<SessionKey>,
+) -> ServerResult<Json<Vec<SessionInfo>>> {
+    Ok(Json(pool.select_sessions(&user_id, &key.hash(&session_token)).await?))
+}
+
+pub async fn revoke_session(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    Path(session_id): Path<Uuid>,
+) -> ServerResult<StatusCode> {
+    if pool.delete_session(&user_id, session_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ServerError::SessionNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::sessions::SessionsTable;
+    use crate::db::test::{DBInit, SESSION1};
+    use crate::middleware::session_token::SessionToken;
+    use crate::middleware::user_id::UserId;
+    use crate::state::SessionKey;
+    use crate::test::{test_app, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use axum::Router;
+    use gph_core::types::SessionInfo;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn err_if_missing_session_token(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let response = app.oneshot(Request::delete("/session").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn session_token_invalid_after_delete(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let app = test_app(pool).await;
+        let response = send(&app, Request::delete("/session")).await?;
+        assert_eq!(response, StatusCode::NO_CONTENT);
+
+        let response = send(&app, Request::get("/user_id")).await?;
+        assert_eq!(response, StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_refresh_session(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let app = test_app(pool).await;
+        let request = Request::post("/session/refresh")
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())?;
+        let response = app.clone().oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let new_token = String::from_utf8(response.into_body().collect().await?.to_bytes().to_vec())?;
+        assert_ne!(new_token, SESSION1);
+        assert!(new_token.starts_with("gph_"));
+
+        let response = send(&app, Request::get("/user_id")).await?;
+        assert_eq!(response, StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_refresh_if_expired(pool: PgPool) -> TestResult {
+        pool.init().await;
+        sqlx::query("UPDATE sessions SET created_at=CURRENT_TIMESTAMP - interval '1000 days'")
+            .execute(&pool)
+            .await?;
+        let app = test_app(pool).await;
+        let request = Request::post("/session/refresh")
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = response.into_body().collect().await?.to_bytes();
+        assert_eq!(body.as_ref(), b"Session expired");
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_list_sessions(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.insert_session(&UserId::USER1, Some("desktop"), &SessionKey::test().hash(&SessionToken::generate())).await?;
+        let app = test_app(pool).await;
+        let request = Request::get("/sessions")
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())?;
+        let response = app.oneshot(request).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let sessions = serde_json::from_slice::<Vec<SessionInfo>>(&body)?;
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions.iter().filter(|session| session.current).count(), 1);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_revoke_other_session(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let desktop = SessionKey::test().hash(&SessionToken::generate());
+        pool.insert_session(&UserId::USER1, Some("desktop"), &desktop).await?;
+        let session_id = pool
+            .select_sessions(&UserId::USER1, &desktop)
+            .await?
+            .into_iter()
+            .find(|session| session.current)
+            .unwrap()
+            .id;
+        let app = test_app(pool.clone()).await;
+        let response = send(&app, Request::delete(format!("/sessions/{session_id}"))).await?;
+        assert_eq!(response, StatusCode::NO_CONTENT);
+        let response = send(&app, Request::delete(format!("/sessions/{session_id}"))).await?;
+        assert_eq!(response, StatusCode::NOT_FOUND);
+
+        let response = send(&app, Request::get("/user_id")).await?;
+        assert_eq!(response, StatusCode::OK);
+        Ok(())
+    }
+
+    async fn send(app: &Router, builder: axum::http::request::Builder) -> TestResult<StatusCode> {
+        let request = builder
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())?;
+        Ok(app.clone().oneshot(request).await?.status())
+    }
+}
@@ -1,54 +1,110 @@
 use crate::db;
+use crate::db::rooms;
 use crate::db::rooms::RoomsTable;
 use crate::error::ServerResult;
+use crate::instance::ServerInstance;
+use crate::metrics::METRICS;
 use crate::middleware::user_id::UserId;
-use axum::extract::ws::{Message, WebSocket};
-use axum::extract::{State, WebSocketUpgrade};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use axum::extract::{Query, State, WebSocketUpgrade};
 use axum::response::IntoResponse;
 use futures_util::stream::{SplitSink, SplitStream};
-use futures_util::{pin_mut, SinkExt, StreamExt};
-use gph_core::types::GitResponse;
+use futures_util::{pin_mut, SinkExt, Stream, StreamExt};
+use gph_core::types::{GitRequest, GitResponse, ShareOptions};
+use sqlx::types::Uuid;
 use sqlx::PgPool;
+use tracing::Instrument;
 
 
 pub async fn share(
     user_id: UserId,
     State(pool): State<PgPool>,
+    State(instance): State<ServerInstance>,
+    Query(options): Query<ShareOptions>,
     ws: WebSocketUpgrade,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |ws| async move {
+) -> ServerResult<impl IntoResponse> {
+    // Rejected before the upgrade so that the owner sees the error.
+    rooms::max_clones(&options)?;
+    let span = tracing::info_span!("share", user_id = user_id.0, repository = options.repository.as_deref());
+    Ok(ws.on_upgrade(move |ws| async move {
+        let _owner = instance.track_owner();
+        METRICS.record_owner_connection();
+        tracing::info!("Owner connected");
         let (mut ws_tx, mut ws_rx) = ws.split();
-        tokio::select! {
-             _ = listen_websocket(&mut ws_rx, &pool) => {},
-            _ = listen_owner_channel(&mut ws_tx, pool.clone(), user_id) => {}
+        let close_frame = match open_share(&mut ws_tx, &mut ws_rx, &pool, &instance, user_id, &options).await {
+            Ok(close_frame) => close_frame,
+            Err(e) => {
+                tracing::error!("Failed to open share({}): {e}", user_id.0);
+                None
+            }
         };
 
-        if let Err(e) = pool.update_room_status(user_id, false).await {
-            tracing::error!("Failed to close room {e}");
+        if close_frame.is_some() {
+            let _ = ws_tx.send(Message::Close(close_frame)).await;
         }
-
         if let Err(e) = ws_tx.close().await {
             tracing::error!("Failed close websocket({}): {e}", user_id.0);
         }
-    })
+        tracing::info!("Owner disconnected");
+    }.instrument(span)))
 }
 
-async fn listen_owner_channel(
-    ws: &mut SplitSink<WebSocket, Message>,
-    pool: PgPool,
+/// Relays git requests until either the owner disconnects, the room is closed from elsewhere
+/// or the server shuts down, in which case the returned close frame tells the owner why.
+async fn open_share(
+    ws_tx: &mut SplitSink<WebSocket, Message>,
+    ws_rx: &mut SplitStream<WebSocket>,
+    pool: &PgPool,
+    instance: &ServerInstance,
     user_id: UserId,
-) -> ServerResult {
-    let stream = db::channel::owner::listen(pool.clone(), user_id).await?;
-    pin_mut!(stream);
+    options: &ShareOptions,
+) -> ServerResult<Option<CloseFrame<'static>>> {
+    let requests = db::channel::owner::listen(pool.clone(), user_id).await?;
+    let room_closed = db::channel::owner::listen_room_closed(pool).await?;
+    let room_id = pool.open_room(user_id, instance.id(), options).await?;
+
+    let close_frame = tokio::select! {
+        _ = listen_websocket(ws_rx, pool) => None,
+        _ = listen_owner_channel(ws_tx, requests) => None,
+        _ = wait_room_closed(room_closed, room_id) => Some(CloseFrame {
+            code: close_code::NORMAL,
+            reason: "The share was closed".into(),
+        }),
+        // Keep relaying responses until the requests already sent to owners are answered.
+        _ = instance.drained() => Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: "The server is shutting down".into(),
+        }),
+    };
 
-    pool.update_room_status(user_id, true).await?;
-    while let Some(git_request) = stream.next().await {
+    if let Err(e) = pool.close_room(user_id, room_id).await {
+        tracing::error!("Failed to close room {e}");
+    }
+    Ok(close_frame)
+}
+
+async fn listen_owner_channel(
+    ws: &mut SplitSink<WebSocket, Message>,
+    requests: impl Stream<Item=GitRequest>,
+) {
+    pin_mut!(requests);
+    while let Some(git_request) = requests.next().await {
+        tracing::debug!(request_id = %git_request.id.0, "Sending git request to owner");
         // If return error, probably websocket has been closed.
         if ws.send(Message::Text(serde_json::to_string(&git_request).unwrap())).await.is_err() {
-            return Ok(());
+            return;
         }
     }
-    Ok(())
+}
+
+async fn wait_room_closed(room_closed: impl Stream<Item=Uuid>, room_id: Uuid) {
+    pin_mut!(room_closed);
+    while let Some(closed_room_id) = room_closed.next().await {
+        if closed_room_id == room_id {
+            return;
+        }
+    }
+    std::future::pending::<()>().await;
 }
 
 async fn listen_websocket(
@@ -62,6 +118,7 @@ async fn listen_websocket(
         let Ok(git_response) = serde_json::from_str::<GitResponse>(message) else {
             continue;
         };
+        tracing::debug!(request_id = %git_response.id.0, "Received git response from owner");
         db::channel::owner::response(pool, &git_response.id, &git_response.output).await?;
     }
     Ok(())
@@ -72,17 +129,23 @@ mod tests {
     use crate::db;
     use crate::db::channel::guest::new_request;
     use crate::db::channel::RequestNotify;
+    use crate::db::rooms::RoomsTable;
     use crate::db::test::{DBInit, SESSION1};
     use crate::middleware::session_token::SessionToken;
     use crate::middleware::user_id::UserId;
-    use crate::test::{start_server, TestResult};
+    use crate::instance::ServerInstance;
+    use crate::state::AppState;
+    use crate::test::{start_server, start_server_with, test_state, TestResult};
     use futures_util::StreamExt;
     use gph_core::types::GitRequest;
     use reqwest::header;
     use sqlx::PgPool;
+    use std::time::Duration;
     use tokio::net::TcpStream;
     use tokio_tungstenite::tungstenite::client::IntoClientRequest;
     use tokio_tungstenite::tungstenite::http::StatusCode;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message;
     use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 
     #[sqlx::test]
@@ -99,15 +162,30 @@ mod tests {
     #[sqlx::test]
     async fn err_if_invalid_user(pool: PgPool) {
         let port = start_server(pool).await;
-        let status_code = connect_expect_err(port, &SessionToken::default()).await;
+        let status_code = connect_expect_err(port, &SessionToken::generate().0).await;
         assert_eq!(status_code, StatusCode::UNAUTHORIZED);
     }
 
+    #[sqlx::test]
+    async fn err_if_max_clones_out_of_range(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let port = start_server(pool).await;
+        let mut request = format!("ws://localhost:{port}/share?max_clones=4294967295").into_client_request()?;
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {SESSION1}").parse()?);
+        let error = connect_async(request).await.unwrap_err();
+        if let tokio_tungstenite::tungstenite::Error::Http(response) = error {
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        } else {
+            panic!("Invalid error type")
+        }
+        Ok(())
+    }
+
     #[sqlx::test]
     async fn ok_open(pool: PgPool) -> TestResult {
         pool.init().await;
         let port = start_server(pool).await;
-        connect(port, &SESSION1).await?;
+        connect(port, SESSION1).await?;
         Ok(())
     }
 
@@ -115,7 +193,7 @@ mod tests {
     async fn ok_recv_request(pool: PgPool) -> TestResult {
         pool.init().await;
         let port = start_server(pool.clone()).await;
-        let mut ws = connect(port, &SESSION1).await?;
+        let mut ws = connect(port, SESSION1).await?;
         let request_body = vec![1, 2, 3];
         let request_id = new_request(&pool, UserId::USER1, &request_body).await?;
         let request_notify = RequestNotify {
@@ -126,6 +204,7 @@ mod tests {
             query_string: None,
             content_length: None,
             content_type: None,
+            remote_addr: None,
         };
         db::channel::guest::request_to_owner(&pool, &request_notify).await?;
         let git_request = ws.next().await.unwrap()?;
@@ -133,7 +212,61 @@ mod tests {
         Ok(())
     }
 
-    async fn connect_expect_err(port: usize, session_token: &SessionToken) -> StatusCode {
+    #[sqlx::test]
+    async fn recv_close_frame_if_room_closed(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let port = start_server(pool.clone()).await;
+        let mut ws = connect(port, SESSION1).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let room = pool.select_open_rooms(UserId::USER1).await?.remove(0);
+        pool.close_room(UserId::USER1, room.id).await?;
+
+        let message = tokio::time::timeout(Duration::from_secs(1), ws.next()).await?.unwrap()?;
+        let Message::Close(Some(close_frame)) = message else {
+            panic!("Expect close frame but was {message:?}");
+        };
+        assert_eq!(close_frame.reason, "The share was closed");
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn recv_close_frame_and_close_room_on_shutdown(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let instance = ServerInstance::new();
+        let port = start_server_with(AppState { instance: instance.clone(), ..test_state(pool.clone()) }).await;
+        let mut ws = connect(port, SESSION1).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        instance.shutdown();
+
+        let message = tokio::time::timeout(Duration::from_secs(1), ws.next()).await?.unwrap()?;
+        let Message::Close(Some(close_frame)) = message else {
+            panic!("Expect close frame but was {message:?}");
+        };
+        assert_eq!(close_frame.code, CloseCode::Away);
+        assert_eq!(close_frame.reason, "The server is shutting down");
+        tokio::time::timeout(Duration::from_secs(1), instance.owners_disconnected()).await?;
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn wait_in_flight_request_before_close_on_shutdown(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let instance = ServerInstance::new();
+        let port = start_server_with(AppState { instance: instance.clone(), ..test_state(pool.clone()) }).await;
+        let mut ws = connect(port, SESSION1).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let request = instance.track_request();
+        instance.shutdown();
+        assert!(tokio::time::timeout(Duration::from_millis(200), ws.next()).await.is_err());
+
+        drop(request);
+        let message = tokio::time::timeout(Duration::from_secs(1), ws.next()).await?.unwrap()?;
+        assert!(matches!(message, Message::Close(Some(_))));
+        Ok(())
+    }
+
+    async fn connect_expect_err(port: usize, session_token: &str) -> StatusCode {
         let error = connect(port, session_token)
             .await
             .unwrap_err();
@@ -144,9 +277,9 @@ mod tests {
         }
     }
 
-    async fn connect(port: usize, session_token: &SessionToken) -> tokio_tungstenite::tungstenite::Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    async fn connect(port: usize, session_token: &str) -> tokio_tungstenite::tungstenite::Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
         let mut request = format!("ws://localhost:{port}/share").into_client_request()?;
-        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {}", session_token.0).parse()?);
+        request.headers_mut().insert(header::AUTHORIZATION, format!("Bearer {session_token}").parse()?);
         connect_async(request)
             .await
             .map(|(ws, _)| ws)
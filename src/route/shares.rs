@@ -0,0 +1,135 @@
+use crate::config::ServerConfig;
+use crate::db::rooms::RoomsTable;
+use crate::error::{ServerError, ServerResult};
+use crate::middleware::user_id::UserId;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use gph_core::types::ShareInfo;
+use sqlx::types::Uuid;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn list_shares(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<ServerConfig>>,
+) -> ServerResult<Json<Vec<ShareInfo>>> {
+    let shares = pool.select_open_rooms(user_id).await?;
+    Ok(Json(shares.into_iter().map(|share| with_remote_url(share, user_id, &config)).collect()))
+}
+
+pub async fn get_share(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<ServerConfig>>,
+    Path(room_id): Path<Uuid>,
+) -> ServerResult<Json<ShareInfo>> {
+    let share = pool.select_open_room(user_id, room_id).await?;
+    Ok(Json(with_remote_url(share, user_id, &config)))
+}
+
+fn with_remote_url(mut share: ShareInfo, user_id: UserId, config: &ServerConfig) -> ShareInfo {
+    share.remote_url = share
+        .repository
+        .as_deref()
+        .and_then(|repository| config.remote_url(user_id.0, repository));
+    share
+}
+
+pub async fn close_share(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    Path(room_id): Path<Uuid>,
+) -> ServerResult<StatusCode> {
+    if pool.close_room(user_id, room_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(ServerError::ShareNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::rooms::RoomsTable;
+    use crate::db::test::{DBInit, INSTANCE1, SESSION1};
+    use crate::middleware::user_id::UserId;
+    use crate::test::{test_app, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use gph_core::types::{ShareInfo, ShareOptions};
+    use http_body_util::BodyExt;
+    use sqlx::types::Uuid;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn err_if_missing_session_token(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let response = app.oneshot(Request::get("/shares").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_list_shares(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let app = test_app(pool).await;
+        let response = app.oneshot(authorized(Request::get("/shares"))?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let shares = serde_json::from_slice::<Vec<ShareInfo>>(&body)?;
+        assert_eq!(shares.len(), 1);
+        assert_eq!(shares[0].id, room_id);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_get_share(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let app = test_app(pool).await;
+        let response = app.oneshot(authorized(Request::get(format!("/shares/{room_id}")))?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_get_share_if_not_exists(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let app = test_app(pool).await;
+        let response = app.oneshot(authorized(Request::get(format!("/shares/{}", Uuid::max())))?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_close_share(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId::USER1, INSTANCE1, &ShareOptions::default()).await?;
+        let app = test_app(pool.clone()).await;
+        let response = app.oneshot(authorized(Request::delete(format!("/shares/{room_id}")))?).await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(!pool.is_open_room(UserId::USER1).await?);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn err_close_share_of_other_user(pool: PgPool) -> TestResult {
+        pool.init().await;
+        let room_id = pool.open_room(UserId(2), INSTANCE1, &ShareOptions::default()).await?;
+        let app = test_app(pool.clone()).await;
+        let response = app.oneshot(authorized(Request::delete(format!("/shares/{room_id}")))?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(pool.is_open_room(UserId(2)).await?);
+        Ok(())
+    }
+
+    fn authorized(builder: axum::http::request::Builder) -> axum::http::Result<Request> {
+        builder
+            .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+            .body(Body::empty())
+    }
+}
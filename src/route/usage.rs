@@ -0,0 +1,66 @@
+use crate::config::ServerConfig;
+use crate::db::transfer_usage::TransferUsageTable;
+use crate::error::ServerResult;
+use crate::middleware::user_id::UserId;
+use axum::extract::State;
+use axum::Json;
+use gph_core::types::TransferUsage;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+pub async fn usage(
+    user_id: UserId,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<ServerConfig>>,
+) -> ServerResult<Json<TransferUsage>> {
+    let usage = pool.select_transfer_usage(user_id).await?;
+    Ok(Json(TransferUsage {
+        daily_bytes: usage.daily_bytes as u64,
+        monthly_bytes: usage.monthly_bytes as u64,
+        daily_quota: config.relay.daily_quota_bytes,
+        monthly_quota: config.relay.monthly_quota_bytes,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::RelayConfig;
+    use crate::db::test::{DBInit, SESSION1};
+    use crate::db::transfer_usage::TransferUsageTable;
+    use crate::middleware::user_id::UserId;
+    use crate::test::{test_app, TestResult};
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::{header, StatusCode};
+    use gph_core::types::TransferUsage;
+    use http_body_util::BodyExt;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    #[sqlx::test]
+    async fn err_if_missing_session_token(pool: PgPool) -> TestResult {
+        let app = test_app(pool).await;
+        let response = app.oneshot(Request::get("/usage").body(Body::empty())?).await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn ok_fetch_usage(pool: PgPool) -> TestResult {
+        pool.init().await;
+        pool.add_transfer(UserId::USER1, 1024).await?;
+        let app = test_app(pool).await;
+        let response = app
+            .oneshot(Request::get("/usage")
+                .header(header::AUTHORIZATION, format!("Bearer {SESSION1}"))
+                .body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let usage = serde_json::from_slice::<TransferUsage>(&body)?;
+        assert_eq!(usage.daily_bytes, 1024);
+        assert_eq!(usage.monthly_bytes, 1024);
+        assert_eq!(usage.daily_quota, RelayConfig::default().daily_quota_bytes);
+        Ok(())
+    }
+}
@@ -1,27 +1,79 @@
+use crate::middleware::session_token::{SessionToken, SessionTokenHash};
 use axum::extract::FromRef;
-use oauth2::{ClientId, ClientSecret};
+use crate::config::ServerConfig;
+use crate::identity::IdentityProviders;
+use crate::instance::ServerInstance;
+use crate::middleware::rate_limit::RateLimits;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
 
-#[derive(Clone)]
-pub struct GithubCredentials {
-    pub client_id: ClientId,
-    pub client_secret: ClientSecret,
+/// How long a session token stays valid since it was issued (`absolute`) and since it was last used (`idle`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SessionExpiry {
+    pub absolute: Duration,
+    pub idle: Duration,
 }
 
-impl GithubCredentials {
-    pub fn load() -> GithubCredentials {
-        use std::env;
-        GithubCredentials {
-            client_id: ClientId::new(env::var("CLIENT_ID").unwrap()),
-            client_secret: ClientSecret::new(env::var("CLIENT_SECRET").unwrap()),
+impl SessionExpiry {
+    pub fn load() -> SessionExpiry {
+        fn secs(key: &str, default: Duration) -> Duration {
+            std::env::var(key)
+                .ok()
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        }
+        let default = SessionExpiry::default();
+        SessionExpiry {
+            absolute: secs("SESSION_ABSOLUTE_EXPIRY_SECS", default.absolute),
+            idle: secs("SESSION_IDLE_EXPIRY_SECS", default.idle),
         }
     }
 }
 
+impl Default for SessionExpiry {
+    fn default() -> Self {
+        const DAY: u64 = 60 * 60 * 24;
+        Self {
+            absolute: Duration::from_secs(DAY * 90),
+            idle: Duration::from_secs(DAY * 30),
+        }
+    }
+}
+
+/// The server secret used to hash session tokens, so that a leaked `sessions` table cannot be used to sign in.
+#[derive(Clone)]
+pub struct SessionKey(Vec<u8>);
+
+impl SessionKey {
+    pub fn new(key: &str) -> SessionKey {
+        SessionKey(key.as_bytes().to_vec())
+    }
+
+    #[cfg(test)]
+    pub fn test() -> SessionKey {
+        SessionKey(b"test".to_vec())
+    }
+
+    pub fn hash(&self, session_token: &SessionToken) -> SessionTokenHash {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(session_token.0.as_bytes());
+        SessionTokenHash(mac.finalize().into_bytes().to_vec())
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: PgPool,
-    pub github_credentials: GithubCredentials,
+    pub identity_providers: IdentityProviders,
+    pub session_expiry: SessionExpiry,
+    pub session_key: SessionKey,
+    pub config: Arc<ServerConfig>,
+    pub instance: ServerInstance,
+    pub rate_limits: RateLimits,
 }
 
 impl FromRef<AppState> for PgPool {
@@ -31,9 +83,37 @@ impl FromRef<AppState> for PgPool {
     }
 }
 
-impl FromRef<AppState> for GithubCredentials {
+impl FromRef<AppState> for IdentityProviders {
+    #[inline]
+    fn from_ref(input: &AppState) -> Self {
+        input.identity_providers.clone()
+    }
+}
+
+impl FromRef<AppState> for SessionExpiry {
+    #[inline]
+    fn from_ref(input: &AppState) -> Self {
+        input.session_expiry
+    }
+}
+
+impl FromRef<AppState> for SessionKey {
+    #[inline]
+    fn from_ref(input: &AppState) -> Self {
+        input.session_key.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<ServerConfig> {
     #[inline]
     fn from_ref(input: &AppState) -> Self {
-        input.github_credentials.clone()
+        input.config.clone()
     }
-}
\ No newline at end of file
+}
+
+impl FromRef<AppState> for ServerInstance {
+    #[inline]
+    fn from_ref(input: &AppState) -> Self {
+        input.instance.clone()
+    }
+}
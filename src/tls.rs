@@ -0,0 +1,151 @@
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Keeps `rustls_config` up to date with the certificate files.
+///
+/// The files are reloaded when their modification time changes, checked every `interval`,
+/// and on `SIGHUP`. Handshakes after a reload use the new certificate while
+/// established connections, such as the owners' websockets, are left open.
+/// A failed reload keeps the previous certificate.
+pub fn spawn_reloader(rustls_config: RustlsConfig, cert_path: PathBuf, key_path: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut watcher = CertWatcher::new(&cert_path, &key_path);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker.tick().await;
+        let mut hangup = hangup_signal();
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    if !watcher.changed(&cert_path, &key_path) {
+                        continue;
+                    }
+                }
+                _ = hangup.recv() => {
+                    watcher = CertWatcher::new(&cert_path, &key_path);
+                }
+            }
+            match rustls_config.reload_from_pem_file(&cert_path, &key_path).await {
+                Ok(()) => tracing::info!("Reloaded TLS certificate from {}", cert_path.display()),
+                Err(e) => tracing::error!("Failed to reload TLS certificate: {e}"),
+            }
+        }
+    });
+}
+
+/// The modification times of the certificate and key last seen.
+#[derive(Debug, PartialEq, Eq)]
+struct CertWatcher {
+    cert_modified: Option<SystemTime>,
+    key_modified: Option<SystemTime>,
+}
+
+impl CertWatcher {
+    fn new(cert_path: &Path, key_path: &Path) -> Self {
+        Self {
+            cert_modified: modified(cert_path),
+            key_modified: modified(key_path),
+        }
+    }
+
+    /// Returns true if either file was modified since the last call.
+    ///
+    /// Renewal tools often write the certificate and key one after the other,
+    /// so a change is only reported once both files can be read.
+    fn changed(&mut self, cert_path: &Path, key_path: &Path) -> bool {
+        let current = Self::new(cert_path, key_path);
+        if current.cert_modified.is_none() || current.key_modified.is_none() || current == *self {
+            return false;
+        }
+        *self = current;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(unix)]
+fn hangup_signal() -> Hangup {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Hangup(Some(signal)),
+        Err(e) => {
+            tracing::error!("Failed to listen for SIGHUP: {e}");
+            Hangup(None)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn hangup_signal() -> Hangup {
+    Hangup
+}
+
+#[cfg(unix)]
+struct Hangup(Option<tokio::signal::unix::Signal>);
+
+#[cfg(not(unix))]
+struct Hangup;
+
+impl Hangup {
+    #[cfg(unix)]
+    async fn recv(&mut self) {
+        match &mut self.0 {
+            Some(signal) => {
+                signal.recv().await;
+            }
+            None => std::future::pending().await,
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn recv(&mut self) {
+        std::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::CertWatcher;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn detect_modified_files() {
+        let dir = std::env::temp_dir().join(format!("gph-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+        std::fs::write(&cert, "cert").unwrap();
+        std::fs::write(&key, "key").unwrap();
+
+        let mut watcher = CertWatcher::new(&cert, &key);
+        assert!(!watcher.changed(&cert, &key));
+
+        let later = SystemTime::now() + Duration::from_secs(10);
+        std::fs::File::options().write(true).open(&cert).unwrap().set_modified(later).unwrap();
+        assert!(watcher.changed(&cert, &key));
+        assert!(!watcher.changed(&cert, &key));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn not_changed_while_file_missing() {
+        let dir = std::env::temp_dir().join(format!("gph-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+        std::fs::write(&cert, "cert").unwrap();
+
+        let mut watcher = CertWatcher::new(&cert, &key);
+        std::fs::File::options().write(true).open(&cert).unwrap().set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        assert!(!watcher.changed(&cert, &key));
+
+        std::fs::write(&key, "key").unwrap();
+        assert!(watcher.changed(&cert, &key));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}